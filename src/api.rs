@@ -0,0 +1,453 @@
+use crate::config::DEFAULT_HOST;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Local};
+use colored::Colorize;
+use http::header::USER_AGENT;
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use http_body_util::BodyExt;
+use hyper_rustls::HttpsConnectorBuilder;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use octocrab::service::middleware::auth_header::AuthHeaderLayer;
+use octocrab::service::middleware::base_uri::BaseUriLayer;
+use octocrab::service::middleware::extra_headers::ExtraHeadersLayer;
+use octocrab::{AuthState, Octocrab, OctocrabBuilder};
+use std::future::{Future, poll_fn};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::Poll;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tower::{Layer, Service};
+use tower_http::follow_redirect::FollowRedirectLayer;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+const API_URL: &str = "https://api.github.com";
+const UPLOAD_URL: &str = "https://uploads.github.com";
+/// How often a request is sent again before its failure is reported
+const MAX_RETRIES: u32 = 4;
+/// Wait before the first retry of a server error, doubled for every further one
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+/// Wait for a secondary rate limit that doesn't say how long, GitHub asks for at least a minute
+const SECONDARY_LIMIT_WAIT: Duration = Duration::from_secs(60);
+/// Rate limits that reset later than this fail the command instead of blocking it
+const MAX_WAIT: Duration = Duration::from_secs(120);
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Prints every API request with the rate limit quota left after it, for `--verbose`
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Base URIs of the REST API and of uploads for a host: github.com's own, or the `/api/v3` and
+/// `/api/uploads` paths of a GitHub Enterprise Server
+pub fn endpoints(host: &str) -> Result<(Uri, Uri)> {
+    if host.eq_ignore_ascii_case(DEFAULT_HOST) {
+        return Ok((Uri::from_static(API_URL), Uri::from_static(UPLOAD_URL)));
+    }
+    let api = format!("https://{host}/api/v3")
+        .parse()
+        .with_context(|| format!("'{host}' is not a valid host name"))?;
+    let uploads = format!("https://{host}/api/uploads")
+        .parse()
+        .with_context(|| format!("'{host}' is not a valid host name"))?;
+    Ok((api, uploads))
+}
+
+/// An Octocrab client for the GitHub API of `host` whose requests are retried on rate limits and
+/// server errors. `token` is sent as a bearer token, `auth` is for the authentication Octocrab
+/// handles itself, such as signing requests as a GitHub App.
+pub fn client(token: Option<&str>, host: &str, auth: AuthState) -> Result<Octocrab> {
+    let (base_uri, upload_uri) = endpoints(host)?;
+    let connector = HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .with_context(|| "Failed to load the system's root certificates")?
+        .https_or_http()
+        .enable_http1()
+        .build();
+    let http = Client::builder(TokioExecutor::new()).build(connector);
+    let auth_header = token
+        .map(|token| HeaderValue::from_str(&format!("Bearer {token}")))
+        .transpose()
+        .with_context(|| "The GitHub token contains invalid characters")?;
+    let user_agent =
+        HeaderValue::from_static(concat!("git-issue-flow/", env!("CARGO_PKG_VERSION")));
+    let client = OctocrabBuilder::new_empty()
+        .with_service(http)
+        .with_layer(&RetryLayer)
+        .with_layer(&FollowRedirectLayer::new())
+        .with_layer(&ExtraHeadersLayer::new(Arc::new(vec![(
+            USER_AGENT, user_agent,
+        )])))
+        .with_layer(&BaseUriLayer::new(base_uri.clone()))
+        .with_layer(&AuthHeaderLayer::new(auth_header, base_uri, upload_uri))
+        .with_auth(auth)
+        .build()?;
+    Ok(client)
+}
+
+/// The rate limit quota GitHub reports with every response
+#[derive(Debug, PartialEq)]
+struct Quota {
+    remaining: u64,
+    limit: u64,
+    /// Unix time the quota is refilled at
+    reset: u64,
+    /// e.g. `core`, `search` or `graphql`, each has its own quota
+    resource: Option<String>,
+}
+
+impl Quota {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        Some(Quota {
+            remaining: header_number(headers, "x-ratelimit-remaining")?,
+            limit: header_number(headers, "x-ratelimit-limit")?,
+            reset: header_number(headers, "x-ratelimit-reset")?,
+            resource: headers
+                .get("x-ratelimit-resource")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        })
+    }
+    fn until_reset(&self, now: SystemTime) -> Duration {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        // A second more, GitHub's clock and ours are rarely in sync
+        Duration::from_secs(self.reset.saturating_sub(now) + 1)
+    }
+}
+
+fn header_number(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Why a request is sent again and after how long
+#[derive(Debug, PartialEq)]
+enum Wait {
+    RateLimit(Duration),
+    ServerError(Duration),
+}
+
+/// How long to wait before sending a request again after the `attempt`th retry, `None` when its
+/// response should be kept
+fn retry_wait(
+    method: &Method,
+    status: StatusCode,
+    headers: &HeaderMap,
+    attempt: u32,
+    now: SystemTime,
+) -> Option<Wait> {
+    if attempt >= MAX_RETRIES {
+        return None;
+    }
+    let retry_after = header_number(headers, "retry-after").map(Duration::from_secs);
+    let exhausted = Quota::from_headers(headers).filter(|quota| quota.remaining == 0);
+    // 403 also means a missing permission, only the headers tell a rate limit apart
+    let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN && (retry_after.is_some() || exhausted.is_some()));
+    if rate_limited {
+        let wait = retry_after
+            .or_else(|| exhausted.map(|quota| quota.until_reset(now)))
+            .unwrap_or(SECONDARY_LIMIT_WAIT * 2u32.pow(attempt));
+        return (wait <= MAX_WAIT).then_some(Wait::RateLimit(wait));
+    }
+    // A request that changes something may have gone through despite the error, sending it again
+    // could do it twice
+    let idempotent = matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+    );
+    if status.is_server_error() && idempotent {
+        let wait = retry_after.unwrap_or(FIRST_BACKOFF * 2u32.pow(attempt));
+        return Some(Wait::ServerError(wait.min(MAX_WAIT)));
+    }
+    None
+}
+
+fn print_quota(method: &Method, uri: &Uri, status: StatusCode, quota: &Quota) {
+    let reset = DateTime::from_timestamp(quota.reset as i64, 0)
+        .map(|reset| reset.with_timezone(&Local).format("%H:%M:%S").to_string())
+        .unwrap_or_default();
+    let resource = quota.resource.as_deref().unwrap_or("core");
+    eprintln!(
+        "{}",
+        format!(
+            "{method} {} {}: {}/{} {resource} requests left, resets at {reset}",
+            uri.path(),
+            status.as_u16(),
+            quota.remaining,
+            quota.limit
+        )
+        .dimmed()
+    );
+}
+
+#[derive(Clone)]
+struct RetryLayer;
+
+impl<S> Layer<S> for RetryLayer {
+    type Service = Retry<S>;
+
+    fn layer(&self, inner: S) -> Retry<S> {
+        Retry { inner }
+    }
+}
+
+/// Sends requests again that failed on a rate limit or a server error, see [`retry_wait`]
+#[derive(Clone)]
+struct Retry<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Retry<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ReqBody: http_body::Body<Data = Bytes> + From<Bytes> + Send + 'static,
+    ReqBody::Error: Into<BoxError>,
+    ResBody: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<ResBody>, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // The service that was polled ready takes this request, a clone takes the next one
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            // Kept in memory so the request can be sent again
+            let body = body.collect().await.map_err(Into::into)?.to_bytes();
+            let mut attempt = 0;
+            loop {
+                let mut request = Request::new(ReqBody::from(body.clone()));
+                *request.method_mut() = parts.method.clone();
+                *request.uri_mut() = parts.uri.clone();
+                *request.version_mut() = parts.version;
+                *request.headers_mut() = parts.headers.clone();
+                if attempt > 0 {
+                    poll_fn(|cx| inner.poll_ready(cx))
+                        .await
+                        .map_err(Into::into)?;
+                }
+                let response = inner.call(request).await.map_err(Into::into)?;
+                let status = response.status();
+                if VERBOSE.load(Ordering::Relaxed)
+                    && let Some(quota) = Quota::from_headers(response.headers())
+                {
+                    print_quota(&parts.method, &parts.uri, status, &quota);
+                }
+                let wait = retry_wait(
+                    &parts.method,
+                    status,
+                    response.headers(),
+                    attempt,
+                    SystemTime::now(),
+                );
+                let delay = match wait {
+                    None => return Ok(response),
+                    Some(Wait::RateLimit(delay)) => {
+                        eprintln!(
+                            "{}",
+                            format!(
+                                "Waiting {}s for GitHub's rate limit to reset",
+                                delay.as_secs()
+                            )
+                            .yellow()
+                        );
+                        delay
+                    }
+                    Some(Wait::ServerError(delay)) => {
+                        eprintln!(
+                            "{}",
+                            format!("GitHub answered {status}, retrying in {}s", delay.as_secs())
+                                .yellow()
+                        );
+                        delay
+                    }
+                };
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::stand_in_server;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn should_wait_for_the_rate_limit_to_reset() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let exhausted = |reset: &str| {
+            headers(&[
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-limit", "5000"),
+                ("x-ratelimit-reset", reset),
+            ])
+        };
+        assert_eq!(
+            retry_wait(
+                &Method::POST,
+                StatusCode::FORBIDDEN,
+                &exhausted("1000030"),
+                0,
+                now
+            ),
+            Some(Wait::RateLimit(Duration::from_secs(31)))
+        );
+        // Resets too far off to keep the user waiting
+        assert_eq!(
+            retry_wait(
+                &Method::GET,
+                StatusCode::FORBIDDEN,
+                &exhausted("1003600"),
+                0,
+                now
+            ),
+            None
+        );
+        let retry_after = headers(&[("retry-after", "5")]);
+        assert_eq!(
+            retry_wait(
+                &Method::GET,
+                StatusCode::TOO_MANY_REQUESTS,
+                &retry_after,
+                1,
+                now
+            ),
+            Some(Wait::RateLimit(Duration::from_secs(5)))
+        );
+        assert_eq!(
+            retry_wait(
+                &Method::GET,
+                StatusCode::TOO_MANY_REQUESTS,
+                &HeaderMap::new(),
+                0,
+                now
+            ),
+            Some(Wait::RateLimit(SECONDARY_LIMIT_WAIT))
+        );
+        // A missing permission
+        let quota_left = headers(&[
+            ("x-ratelimit-remaining", "4999"),
+            ("x-ratelimit-limit", "5000"),
+            ("x-ratelimit-reset", "1000030"),
+        ]);
+        assert_eq!(
+            retry_wait(&Method::GET, StatusCode::FORBIDDEN, &quota_left, 0, now),
+            None
+        );
+    }
+
+    #[test]
+    fn should_back_off_on_server_errors_of_idempotent_requests() {
+        let now = SystemTime::now();
+        let none = HeaderMap::new();
+        assert_eq!(
+            retry_wait(&Method::GET, StatusCode::BAD_GATEWAY, &none, 0, now),
+            Some(Wait::ServerError(Duration::from_secs(1)))
+        );
+        assert_eq!(
+            retry_wait(&Method::GET, StatusCode::BAD_GATEWAY, &none, 2, now),
+            Some(Wait::ServerError(Duration::from_secs(4)))
+        );
+        assert_eq!(
+            retry_wait(
+                &Method::GET,
+                StatusCode::BAD_GATEWAY,
+                &none,
+                MAX_RETRIES,
+                now
+            ),
+            None
+        );
+        assert_eq!(
+            retry_wait(&Method::POST, StatusCode::BAD_GATEWAY, &none, 0, now),
+            None
+        );
+        assert_eq!(
+            retry_wait(&Method::GET, StatusCode::NOT_FOUND, &none, 0, now),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn should_send_the_request_again_after_a_rate_limit() {
+        let (url, requests) = stand_in_server(vec![
+            ("HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0", "{}"),
+            ("HTTP/1.1 201 Created", "{}"),
+        ]);
+        let http = Client::builder(TokioExecutor::new()).build_http();
+        let client = OctocrabBuilder::new_empty()
+            .with_service(http)
+            .with_layer(&RetryLayer)
+            .with_layer(&BaseUriLayer::new(url.parse().unwrap()))
+            .with_auth(AuthState::None)
+            .build()
+            .unwrap();
+        let _: serde_json::Value = client
+            .post("/repos/acme/widgets/issues/1/labels", Some(&["bug"]))
+            .await
+            .unwrap();
+        let request = r#"/repos/acme/widgets/issues/1/labels ["bug"]"#;
+        assert_eq!(*requests.lock().unwrap(), [request, request]);
+    }
+
+    #[test]
+    fn should_use_the_enterprise_api_of_other_hosts() {
+        let (api, uploads) = endpoints("github.com").unwrap();
+        assert_eq!(
+            (api.to_string(), uploads.to_string()),
+            (
+                "https://api.github.com/".to_string(),
+                "https://uploads.github.com/".to_string()
+            )
+        );
+        let (api, uploads) = endpoints("ghe.acme.com").unwrap();
+        assert_eq!(api, "https://ghe.acme.com/api/v3");
+        assert_eq!(uploads, "https://ghe.acme.com/api/uploads");
+        assert!(endpoints("not a host").is_err());
+    }
+
+    #[test]
+    fn should_read_the_quota_from_headers() {
+        let quota = Quota::from_headers(&headers(&[
+            ("x-ratelimit-remaining", "4990"),
+            ("x-ratelimit-limit", "5000"),
+            ("x-ratelimit-reset", "1000030"),
+            ("x-ratelimit-resource", "graphql"),
+        ]));
+        assert_eq!(
+            quota,
+            Some(Quota {
+                remaining: 4990,
+                limit: 5000,
+                reset: 1_000_030,
+                resource: Some("graphql".to_string()),
+            })
+        );
+        assert_eq!(
+            Quota::from_headers(&headers(&[("x-ratelimit-limit", "60")])),
+            None
+        );
+    }
+}
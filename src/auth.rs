@@ -0,0 +1,257 @@
+use crate::github_app::AppInstallation;
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Places a GitHub token can be read from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenSource {
+    /// An installation token of the GitHub App set with `app_id` and `app_private_key`
+    App,
+    /// The token saved with `config --token`, or set in another configuration layer
+    Config,
+    /// `GITHUB_TOKEN` or `GH_TOKEN`
+    Env,
+    /// The GitHub CLI login of the remote host
+    Gh,
+    /// `git credential fill` for the remote host
+    GitCredential,
+}
+
+/// Order the sources are tried in when none is configured
+pub const DEFAULT_TOKEN_SOURCES: [TokenSource; 5] = [
+    TokenSource::App,
+    TokenSource::Config,
+    TokenSource::Env,
+    TokenSource::Gh,
+    TokenSource::GitCredential,
+];
+
+const TOKEN_ENV_VARS: [&str; 2] = ["GITHUB_TOKEN", "GH_TOKEN"];
+
+impl fmt::Display for TokenSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenSource::App => write!(f, "GitHub App"),
+            TokenSource::Config => write!(f, "config"),
+            TokenSource::Env => write!(f, "{}", TOKEN_ENV_VARS.join("/")),
+            TokenSource::Gh => write!(f, "gh CLI"),
+            TokenSource::GitCredential => write!(f, "git credential helper"),
+        }
+    }
+}
+
+/// A token and the source it was found in
+#[derive(Debug)]
+pub struct Token {
+    pub value: String,
+    pub source: TokenSource,
+}
+
+/// Tries the sources in order and returns the first token found. `configured` is the token from
+/// the configuration layers, `host` the host of the origin remote and `app` the GitHub App set up
+/// for the repository, if any.
+pub async fn find_token(
+    sources: &[TokenSource],
+    configured: Option<&str>,
+    host: &str,
+    app: Option<&AppInstallation>,
+) -> Result<Option<Token>> {
+    for &source in sources {
+        let value = match source {
+            TokenSource::App => match app {
+                Some(app) => Some(app.token().await?),
+                None => None,
+            },
+            TokenSource::Config => configured.map(str::to_string),
+            TokenSource::Env => TOKEN_ENV_VARS
+                .iter()
+                .find_map(|var| std::env::var(var).ok())
+                .filter(|token| !token.is_empty()),
+            TokenSource::Gh => gh_token(host)?,
+            TokenSource::GitCredential => git_credential_token(host)?,
+        };
+        if let Some(value) = value {
+            return Ok(Some(Token { value, source }));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GhHost {
+    oauth_token: Option<String>,
+    user: Option<String>,
+    #[serde(default)]
+    users: HashMap<String, GhUser>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GhUser {
+    oauth_token: Option<String>,
+}
+
+/// Token of the gh CLI login for `host`. Older gh versions keep it in `hosts.yml`,
+/// newer ones in the system keyring, where `gh auth token` reads it from.
+fn gh_token(host: &str) -> Result<Option<String>> {
+    if let Some(path) = gh_hosts_path().filter(|path| path.is_file()) {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if let Some(token) = token_from_gh_hosts(&content, host)
+            .with_context(|| format!("Failed to parse {}", path.display()))?
+        {
+            return Ok(Some(token));
+        }
+    }
+    let output = Command::new("gh")
+        .args(["auth", "token", "--hostname", host])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    Ok(match output {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+                .filter(|token| !token.is_empty())
+        }
+        _ => None,
+    })
+}
+
+fn gh_hosts_path() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("GH_CONFIG_DIR") {
+        return Some(PathBuf::from(dir).join("hosts.yml"));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("gh").join("hosts.yml"));
+    }
+    if cfg!(windows) {
+        return dirs::config_dir().map(|dir| dir.join("GitHub CLI").join("hosts.yml"));
+    }
+    dirs::home_dir().map(|dir| dir.join(".config").join("gh").join("hosts.yml"))
+}
+
+fn token_from_gh_hosts(content: &str, host: &str) -> Result<Option<String>> {
+    let mut hosts: HashMap<String, GhHost> = serde_yaml_ng::from_str(content)?;
+    let Some(entry) = hosts.remove(host) else {
+        return Ok(None);
+    };
+    let active_user_token = entry
+        .user
+        .as_ref()
+        .and_then(|user| entry.users.get(user))
+        .and_then(|user| user.oauth_token.clone());
+    Ok(entry.oauth_token.or(active_user_token))
+}
+
+/// Asks the configured git credential helpers for the password of `https://<host>`,
+/// without letting git prompt for one
+fn git_credential_token(host: &str) -> Result<Option<String>> {
+    let child = Command::new("git")
+        .args(["credential", "fill"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GCM_INTERACTIVE", "never")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return Ok(None);
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(format!("protocol=https\nhost={host}\n\n").as_bytes())
+            .with_context(|| "Failed to ask git for credentials")?;
+    }
+    let output = child
+        .wait_with_output()
+        .with_context(|| "Failed to ask git for credentials")?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(password_from_credential_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+fn password_from_credential_output(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("password="))
+        .filter(|password| !password.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_tokens_from_gh_hosts_file() {
+        let legacy = "github.com:\n    oauth_token: gho_legacy\n    user: alice\n";
+        assert_eq!(
+            token_from_gh_hosts(legacy, "github.com").unwrap(),
+            Some("gho_legacy".to_string())
+        );
+        let multi_account = "\
+github.com:
+    git_protocol: https
+    users:
+        alice:
+            oauth_token: gho_alice
+        bob:
+            oauth_token: gho_bob
+    user: bob
+";
+        assert_eq!(
+            token_from_gh_hosts(multi_account, "github.com").unwrap(),
+            Some("gho_bob".to_string())
+        );
+        assert_eq!(
+            token_from_gh_hosts(multi_account, "ghe.corp").unwrap(),
+            None
+        );
+        let keyring_only = "github.com:\n    user: alice\n    git_protocol: ssh\n";
+        assert_eq!(
+            token_from_gh_hosts(keyring_only, "github.com").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn should_read_password_from_credential_output() {
+        let output = "protocol=https\nhost=github.com\nusername=alice\npassword=ghp_123\n";
+        assert_eq!(
+            password_from_credential_output(output),
+            Some("ghp_123".to_string())
+        );
+        assert_eq!(password_from_credential_output("protocol=https\n"), None);
+    }
+
+    #[tokio::test]
+    async fn should_stop_at_the_first_source_with_a_token() {
+        let token = find_token(
+            &[TokenSource::App, TokenSource::Config, TokenSource::Env],
+            Some("from-config"),
+            "github.com",
+            None,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(token.value, "from-config");
+        assert_eq!(token.source, TokenSource::Config);
+        assert!(
+            find_token(&[TokenSource::Config], None, "github.com", None)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
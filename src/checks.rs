@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub command: String,
+    pub passed: bool,
+    pub duration: Duration,
+}
+
+/// Runs every check through the shell in `workdir`, one after another, with their output
+/// going straight to the terminal. A failing check doesn't stop the ones after it.
+pub fn run_checks(commands: &[String], workdir: &Path) -> Result<Vec<CheckResult>> {
+    let mut results = Vec::new();
+    for command in commands {
+        println!("{} {}", "Running".bold(), command);
+        let started = Instant::now();
+        let status = shell(command)
+            .current_dir(workdir)
+            .status()
+            .with_context(|| format!("Failed to run check: {command}"))?;
+        results.push(CheckResult {
+            command: command.clone(),
+            passed: status.success(),
+            duration: started.elapsed(),
+        });
+    }
+    Ok(results)
+}
+
+/// Runs `command` through the platform's shell
+pub fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+pub fn print_summary(results: &[CheckResult]) {
+    println!("\n{}", "Checks:".bold());
+    for result in results {
+        let status = if result.passed {
+            "passed".green()
+        } else {
+            "FAILED".red()
+        };
+        println!(
+            "  {status:<8} {:>8}  {}",
+            format_duration(result.duration),
+            result.command
+        );
+    }
+}
+
+/// Check results as a Markdown table for the pull request description
+pub fn markdown_report(results: &[CheckResult]) -> String {
+    let mut report = String::from("### Checks\n\n| Check | Result | Time |\n| --- | --- | --- |\n");
+    for result in results {
+        let status = if result.passed {
+            "✅ passed"
+        } else {
+            "❌ failed"
+        };
+        report.push_str(&format!(
+            "| `{}` | {status} | {} |\n",
+            result.command.replace('|', "\\|"),
+            format_duration(result.duration)
+        ));
+    }
+    report
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    if seconds < 60.0 {
+        format!("{seconds:.1}s")
+    } else {
+        format!(
+            "{}m {:02}s",
+            duration.as_secs() / 60,
+            duration.as_secs() % 60
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_format_durations() {
+        assert_eq!(format_duration(Duration::from_millis(1234)), "1.2s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m 05s");
+    }
+
+    #[test]
+    fn should_render_results_as_markdown_table() {
+        let results = vec![
+            CheckResult {
+                command: "cargo test".to_string(),
+                passed: true,
+                duration: Duration::from_secs(12),
+            },
+            CheckResult {
+                command: "cargo clippy | tee lint.log".to_string(),
+                passed: false,
+                duration: Duration::from_millis(3400),
+            },
+        ];
+        assert_eq!(
+            markdown_report(&results),
+            "### Checks\n\n| Check | Result | Time |\n| --- | --- | --- |\n\
+             | `cargo test` | ✅ passed | 12.0s |\n\
+             | `cargo clippy \\| tee lint.log` | ❌ failed | 3.4s |\n"
+        );
+    }
+}
@@ -1,9 +1,18 @@
-use clap::{Parser, Subcommand};
+use crate::settings::Scope;
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(name = "git-issue-flow")]
 #[command(about = "A CLI tool for managing Git issues", long_about = None)]
 pub struct Cli {
+    /// Use this profile instead of the one matching the origin remote
+    #[arg(long, global = true, env = "GIT_TASK_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Print every GitHub API request with the rate limit quota left
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -14,16 +23,245 @@ pub enum Commands {
         /// set the GITHUB token
         #[arg(long)]
         token: Option<String>,
+
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
     },
     /// Start working on the issue
-    Start,
+    Start {
+        /// Push the new branch right away and open a draft pull request for it
+        #[arg(long)]
+        draft_pr: bool,
+
+        /// Start the branch from this ref instead of the configured base branch
+        #[arg(long, value_name = "REF")]
+        from: Option<String>,
+
+        /// Check the branch out in a new linked worktree instead of the current one
+        #[arg(long)]
+        worktree: bool,
+    },
+    /// Show the tasks in progress in this repository
+    Status,
+    /// Check the repository, token and credentials the other commands need
+    Doctor,
+    /// Log in to GitHub in the browser and save the token, for the profile in use if there's one
+    Login {
+        /// Client id of the OAuth app to authorize, instead of the oauth_client_id setting
+        #[arg(long)]
+        client_id: Option<String>,
+
+        /// Scope to ask for, can be repeated, defaults to repo
+        #[arg(long = "scope", value_name = "SCOPE")]
+        scopes: Vec<String>,
+    },
+    /// Switch to another task branch
+    Switch,
+    /// Bring the latest base branch into the current task branch
+    Sync {
+        /// Rebase onto the base branch, the default unless configured otherwise
+        #[arg(long, conflicts_with = "merge")]
+        rebase: bool,
+
+        /// Merge the base branch instead of rebasing
+        #[arg(long)]
+        merge: bool,
+
+        /// Push the branch afterwards, with a lease when the history was rewritten
+        #[arg(long)]
+        push: bool,
+    },
+    /// Delete task branches whose pull request was merged or whose issue was closed
+    Cleanup {
+        /// Only show what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Stop working on a task and delete its branch
+    Abandon {
+        /// Issue number of the task, defaults to the task of the current branch
+        issue: Option<u64>,
+
+        /// Leave a comment on the issue explaining why
+        #[arg(short, long)]
+        comment: Option<String>,
+
+        /// Delete the branch on origin as well
+        #[arg(long)]
+        delete_remote: bool,
+    },
+    /// Create a pull request for the current task
     Finish {
         title: Option<String>,
 
         #[arg(short, long)]
         description: Option<String>,
+
+        /// Open the pull request as a draft
+        #[arg(long)]
+        draft: bool,
+
+        /// Open the pull request ready for review, even when the draft setting is on
+        #[arg(long, conflicts_with = "draft")]
+        ready: bool,
+
+        /// Request a review from a user, can be repeated
+        #[arg(long = "reviewer", value_name = "LOGIN")]
+        reviewers: Vec<String>,
+
+        /// Request a review from a team (by slug), can be repeated
+        #[arg(long = "team-reviewer", value_name = "TEAM")]
+        team_reviewers: Vec<String>,
+
+        /// Assign a user to the pull request, can be repeated
+        #[arg(long = "assignee", value_name = "LOGIN")]
+        assignees: Vec<String>,
+
+        /// Add a label to the pull request, can be repeated
+        #[arg(long = "label", value_name = "LABEL")]
+        labels: Vec<String>,
+
+        /// Milestone number to attach the pull request to
+        #[arg(long)]
+        milestone: Option<u64>,
+
+        /// Don't request reviews from the CODEOWNERS of the changed files
+        #[arg(long)]
+        no_auto_reviewers: bool,
+
+        /// Don't run the configured checks before pushing
+        #[arg(long)]
+        skip_checks: bool,
+    },
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Show the settings in effect for the current repository
+    #[command(alias = "list")]
+    Show {
+        /// Tell which file or environment variable each value comes from
+        #[arg(long)]
+        origin: bool,
+    },
+    /// Print the value in effect of a setting
+    Get {
+        key: String,
+
+        /// Tell which file or environment variable the value comes from
+        #[arg(long)]
+        origin: bool,
     },
+    /// Change a setting, lists may be comma separated or JSON
+    Set {
+        key: String,
+        value: String,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Remove a setting from a config file
+    Unset {
+        key: String,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Open a config file in $VISUAL or $EDITOR and check it afterwards
+    Edit {
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Check the config files for unknown keys and invalid values
+    Validate {
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Print the JSON Schema of the TOML settings files, for editor completion
+    Schema {
+        /// Print the schema of the global JSON config file instead
+        #[arg(long)]
+        global: bool,
+    },
+    /// Manage the profiles of your GitHub accounts
+    Profiles {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+}
+
+/// Which config file to work on, the local one when none is picked
+#[derive(Args, Debug)]
+pub struct ScopeArgs {
+    /// The global config file, settings go to this repository's entry
+    #[arg(long, conflicts_with_all = ["repo", "local"])]
+    pub global: bool,
+
+    /// With --global, the top-level settings used by every repository instead of its own entry
+    #[arg(long, requires = "global")]
+    pub all_repos: bool,
+
+    /// `.git-task.toml`, shared with everyone working on the repository
+    #[arg(long, conflicts_with = "local")]
+    pub repo: bool,
+
+    /// `git-task.toml` in the git directory, for this clone only
+    #[arg(long)]
+    pub local: bool,
+}
+
+impl ScopeArgs {
+    /// The scope picked, if any
+    pub fn scope(&self) -> Option<Scope> {
+        if self.global && self.all_repos {
+            Some(Scope::GlobalDefault)
+        } else if self.global {
+            Some(Scope::Global)
+        } else if self.repo {
+            Some(Scope::Repository)
+        } else if self.local {
+            Some(Scope::Local)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProfileAction {
+    /// List the profiles, marking the one used in the current repository
     List,
+    /// Add a profile, or replace the one with the same name
+    Add {
+        name: String,
+
+        /// Host of the remotes the profile is for, defaults to github.com
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Owner (user or organization) the profile is for, wildcards allowed, can be repeated
+        #[arg(long = "owner", value_name = "PATTERN")]
+        owners: Vec<String>,
+
+        /// Label added to pull requests, can be repeated
+        #[arg(long = "label", value_name = "LABEL")]
+        labels: Vec<String>,
+
+        /// Commit author name for repositories using the profile
+        #[arg(long)]
+        user_name: Option<String>,
+
+        /// Commit author email for repositories using the profile
+        #[arg(long)]
+        user_email: Option<String>,
+
+        /// GitHub token of the account, saved with the other secrets
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Remove a profile and its token
+    Remove { name: String },
 }
 
 pub fn parse_args() -> Cli {
@@ -0,0 +1,131 @@
+use crate::pattern::path_matches;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Places GitHub looks for a CODEOWNERS file, the first one found wins
+const CODEOWNERS_LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+#[derive(Debug, Default)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: String,
+    owners: Vec<String>,
+}
+
+/// Users and team slugs that should review a change
+#[derive(Debug, Default, PartialEq)]
+pub struct Reviewers {
+    pub users: Vec<String>,
+    pub teams: Vec<String>,
+}
+
+impl CodeOwners {
+    /// Loads the CODEOWNERS file of a working directory, if there is one
+    pub fn find(workdir: &Path) -> Result<Option<Self>> {
+        for location in CODEOWNERS_LOCATIONS {
+            let path = workdir.join(location);
+            if path.is_file() {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {path:?}"))?;
+                return Ok(Some(CodeOwners::parse(&content)));
+            }
+        }
+        Ok(None)
+    }
+    pub fn parse(content: &str) -> Self {
+        let rules = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let line = line.split(" #").next().unwrap_or(line);
+                let mut parts = line.split_whitespace();
+                let pattern = parts.next()?.to_string();
+                let owners = parts.map(str::to_string).collect();
+                Some(Rule { pattern, owners })
+            })
+            .collect();
+        CodeOwners { rules }
+    }
+    /// Owners of a single path. The last matching rule wins, and it may have no owners at all
+    pub fn owners_of(&self, path: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| path_matches(&rule.pattern, path))
+            .map(|rule| rule.owners.as_slice())
+            .unwrap_or_default()
+    }
+    /// Collects the users and teams owning any of the paths.
+    /// Email owners can't be requested as reviewers and are skipped, as is `exclude` (the PR author).
+    pub fn reviewers_for<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a str>,
+        exclude: &str,
+    ) -> Reviewers {
+        let mut reviewers = Reviewers::default();
+        for path in paths {
+            for owner in self.owners_of(path) {
+                let Some(name) = owner.strip_prefix('@') else {
+                    continue;
+                };
+                let (list, name) = match name.split_once('/') {
+                    Some((_org, team)) => (&mut reviewers.teams, team),
+                    None if name.eq_ignore_ascii_case(exclude) => continue,
+                    None => (&mut reviewers.users, name),
+                };
+                if !list.iter().any(|existing| existing == name) {
+                    list.push(name.to_string());
+                }
+            }
+        }
+        reviewers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODEOWNERS: &str = "\
+# Default owners
+*       @acme/core
+
+*.rs    @alice @bob # rust folks
+/docs/  docs@acme.com @carol
+/src/generated/
+";
+
+    #[test]
+    fn should_use_the_last_matching_rule() {
+        let owners = CodeOwners::parse(CODEOWNERS);
+        assert_eq!(owners.owners_of("README.md"), ["@acme/core"]);
+        assert_eq!(owners.owners_of("src/main.rs"), ["@alice", "@bob"]);
+        assert_eq!(
+            owners.owners_of("docs/intro.md"),
+            ["docs@acme.com", "@carol"]
+        );
+        assert!(owners.owners_of("src/generated/api.rs").is_empty());
+    }
+
+    #[test]
+    fn should_split_users_and_teams_and_skip_the_author() {
+        let owners = CodeOwners::parse(CODEOWNERS);
+        let reviewers = owners.reviewers_for(
+            ["README.md", "src/main.rs", "src/git.rs", "docs/intro.md"],
+            "Bob",
+        );
+        assert_eq!(
+            reviewers,
+            Reviewers {
+                users: vec!["alice".to_string(), "carol".to_string()],
+                teams: vec!["core".to_string()],
+            }
+        );
+    }
+}
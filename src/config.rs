@@ -1,15 +1,161 @@
-use anyhow::{Context, Result};
+use crate::auth::{DEFAULT_TOKEN_SOURCES, TokenSource};
+use crate::labels::{LabelRule, SizeThresholds};
+use crate::pattern::wildcard_match;
+use crate::secrets::{GITHUB_TOKEN_SECRET, SecretLocation, remove_secret, store_secret};
+use anyhow::{Context, Result, anyhow};
 use dirs::config_dir;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[derive(Default)]
+#[schemars(deny_unknown_fields)]
 pub struct Config {
+    /// Only read from older config files, the token now lives with the other secrets
+    #[serde(default, skip_serializing)]
+    #[schemars(skip)]
     pub github_token: Option<String>,
+    /// Accounts with their own token and defaults, keyed by profile name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Per-repository settings keyed by `owner/repo`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub repos: HashMap<String, RepoConfig>,
+    /// Settings for every repository, below its entry in `repos`
+    #[serde(flatten)]
+    pub defaults: RepoConfig,
 }
 
+/// Host of remotes when a profile doesn't name one
+pub const DEFAULT_HOST: &str = "github.com";
+/// Branch that tasks start from and pull requests target when none is configured
+pub const DEFAULT_BASE_BRANCH: &str = "main";
+/// Name of task branches when no template is configured
+pub const DEFAULT_BRANCH_TEMPLATE: &str = "feature/{number}";
+/// Where `login` finds GitHub's OAuth endpoints when no other server is configured
+pub const DEFAULT_OAUTH_URL: &str = "https://github.com";
+/// Where `start --worktree` puts worktrees, relative to the main working directory
+pub const DEFAULT_WORKTREE_DIR: &str = "../{repo}-{number}";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct RepoConfig {
+    /// Branch that tasks start from and pull requests target
+    pub base_branch: Option<String>,
+    /// Name of task branches, `{number}` and `{slug}` (from the issue title) get filled in
+    pub branch_template: Option<String>,
+    /// Open pull requests as drafts by default
+    pub draft: bool,
+    pub reviewers: Vec<String>,
+    pub team_reviewers: Vec<String>,
+    pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+    pub milestone: Option<u64>,
+    /// Directory template for task worktrees, `{repo}` and `{number}` get filled in
+    pub worktree_dir: Option<String>,
+    /// Path based labels, e.g. `src/api/** -> area:api`
+    pub label_rules: Vec<LabelRule>,
+    /// Adds a size label (`XS`..`XL`) by the number of changed lines when set
+    pub size_labels: Option<SizeThresholds>,
+    /// How `sync` brings the base branch into the task branch
+    pub sync_strategy: SyncStrategy,
+    /// Shell commands `finish` runs before pushing, e.g. `cargo test`, all of them have to pass
+    pub checks: Vec<String>,
+    /// Adds a table with the check results to the pull request description
+    pub checks_in_pr_body: bool,
+    /// Where to look for a GitHub token, in order: `app`, `config`, `env`, `gh`, `git-credential`
+    pub token_sources: Vec<TokenSource>,
+    /// Client id of the OAuth app `login` authorizes, the app needs the device flow enabled
+    pub oauth_client_id: Option<String>,
+    /// Server with the OAuth endpoints, e.g. a GitHub Enterprise host
+    pub oauth_url: Option<String>,
+    /// Id of the GitHub App to act as, with `app_private_key`
+    pub app_id: Option<u64>,
+    /// PEM file with the private key of the GitHub App, a relative path is taken from the directory
+    /// of the config file that sets it
+    pub app_private_key: Option<String>,
+    /// Installation of the GitHub App to use, looked up from the repository when not set
+    pub app_installation_id: Option<u64>,
+}
+
+/// A GitHub account, e.g. work or personal. It's used for the repositories whose `origin` matches
+/// its host and owners, or when picked with `--profile`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct Profile {
+    /// Host of the remotes the profile is for, `github.com` when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Owners (users or organizations) the profile is for, `*` and `?` match any characters.
+    /// Without any, every repository on the host matches.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub owners: Vec<String>,
+    /// Labels added to pull requests unless the repository configures its own
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// Commit author set in the repositories tasks are started in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    /// Commit author email set along with `user_name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_email: Option<String>,
+}
+
+impl Profile {
+    pub fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or(DEFAULT_HOST)
+    }
+    fn matches(&self, host: &str, owner: Option<&str>) -> bool {
+        if !self.host().eq_ignore_ascii_case(host) {
+            return false;
+        }
+        self.owners.is_empty()
+            || owner.is_some_and(|owner| {
+                self.owners
+                    .iter()
+                    .any(|pattern| wildcard_match(&pattern.to_lowercase(), &owner.to_lowercase()))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncStrategy {
+    #[default]
+    Rebase,
+    Merge,
+}
+
+
+impl RepoConfig {
+    pub fn base_branch(&self) -> &str {
+        self.base_branch.as_deref().unwrap_or(DEFAULT_BASE_BRANCH)
+    }
+    pub fn branch_template(&self) -> &str {
+        self.branch_template
+            .as_deref()
+            .unwrap_or(DEFAULT_BRANCH_TEMPLATE)
+    }
+    pub fn token_sources(&self) -> &[TokenSource] {
+        if self.token_sources.is_empty() {
+            &DEFAULT_TOKEN_SOURCES
+        } else {
+            &self.token_sources
+        }
+    }
+    pub fn oauth_url(&self) -> &str {
+        self.oauth_url.as_deref().unwrap_or(DEFAULT_OAUTH_URL)
+    }
+    pub fn worktree_dir(&self) -> &str {
+        self.worktree_dir.as_deref().unwrap_or(DEFAULT_WORKTREE_DIR)
+    }
+}
 
 impl Config {
     pub fn load() -> Result<Self> {
@@ -19,31 +165,133 @@ impl Config {
         }
         let config_str = fs::read_to_string(&config_path)
             .with_context(|| format!("Failed to read config file: {config_path:?}"))?;
-        let config = serde_json::from_str(&config_str)
+        let mut config: Config = serde_json::from_str(&config_str)
             .with_context(|| "Failed to parse config file as JSON")?;
+        if let Some(token) = config.github_token.take() {
+            migrate_plaintext_token(&config_path, &token)?;
+        }
 
         Ok(config)
     }
-    pub fn save(&self) -> Result<()> {
-        let config_path = get_config_path()?;
-        if let Some(parent) = config_path.parent() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create a config directory: {parent:?}"))?;
+    /// Saves the token with the other secrets, never in the config file
+    pub fn set_github_token(token: &str) -> Result<SecretLocation> {
+        store_secret(GITHUB_TOKEN_SECRET, token)
+    }
+    /// The profile named, or else the one whose host and owners match the remote. A profile
+    /// listing owners wins over one that takes every repository on the host.
+    pub fn select_profile(
+        &self,
+        name: Option<&str>,
+        host: &str,
+        owner: Option<&str>,
+    ) -> Result<Option<(&str, &Profile)>> {
+        if let Some(name) = name {
+            return match self.profiles.get_key_value(name) {
+                Some((name, profile)) => Ok(Some((name.as_str(), profile))),
+                None => Err(anyhow!(
+                    "Profile '{name}' not found, see 'config profiles list'"
+                )),
+            };
         }
-        let config_str = serde_json::to_string_pretty(self)
-            .with_context(|| "Failed to serialize config to JSON")?;
-        fs::write(&config_path, config_str)
-            .with_context(|| format!("Failed to write config file: {config_path:?}"))?;
-
-        Ok(())
+        let mut matching: Vec<(&str, &Profile)> = self
+            .profiles
+            .iter()
+            .filter(|(_, profile)| profile.matches(host, owner))
+            .map(|(name, profile)| (name.as_str(), profile))
+            .collect();
+        matching.sort_by_key(|(_, profile)| profile.owners.is_empty());
+        Ok(matching.first().copied())
+    }
+    /// Adds the profile, or replaces the one with the same name
+    pub fn save_profile(name: &str, profile: &Profile) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "Invalid profile name '{name}', use letters, digits, '-' and '_'"
+            ));
+        }
+        let profile = serde_json::to_value(profile)?;
+        edit_config_file(|config| {
+            let profiles = config
+                .entry("profiles")
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(profiles) = profiles {
+                profiles.insert(name.to_string(), profile);
+            }
+        })
     }
-    pub fn set_github_token(&mut self, token: String) -> Result<()> {
-        self.github_token = Some(token);
-        self.save()
+    /// Removes the profile and its token, returns whether it existed
+    pub fn remove_profile(name: &str) -> Result<bool> {
+        let mut removed = false;
+        edit_config_file(|config| {
+            if let Some(Value::Object(profiles)) = config.get_mut("profiles") {
+                removed = profiles.remove(name).is_some();
+                if profiles.is_empty() {
+                    config.remove("profiles");
+                }
+            }
+        })?;
+        remove_secret(&profile_token_secret(name))?;
+        Ok(removed)
+    }
+    pub fn set_profile_token(name: &str, token: &str) -> Result<SecretLocation> {
+        store_secret(&profile_token_secret(name), token)
+    }
+}
+
+/// Name the token of a profile is saved under
+pub fn profile_token_secret(profile: &str) -> String {
+    format!("{GITHUB_TOKEN_SECRET}.{profile}")
+}
+
+/// Rewrites the config file with `edit` applied to its JSON. Going through the raw JSON keeps
+/// everything that isn't edited as it was written, instead of filling in every default.
+pub fn edit_config_file(edit: impl FnOnce(&mut Map<String, Value>)) -> Result<()> {
+    let config_path = get_config_path()?;
+    let mut config = if config_path.exists() {
+        let config_str = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read config file: {config_path:?}"))?;
+        match serde_json::from_str(&config_str)
+            .with_context(|| "Failed to parse config file as JSON")?
+        {
+            Value::Object(config) => config,
+            _ => {
+                return Err(anyhow!(
+                    "Config file {config_path:?} should contain a JSON object"
+                ));
+            }
+        }
+    } else {
+        Map::new()
+    };
+    edit(&mut config);
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create a config directory: {parent:?}"))?;
     }
+    let config_str = serde_json::to_string_pretty(&config)
+        .with_context(|| "Failed to serialize config to JSON")?;
+    fs::write(&config_path, config_str)
+        .with_context(|| format!("Failed to write config file: {config_path:?}"))?;
+
+    Ok(())
+}
+
+/// Moves a token saved in plaintext by earlier versions into the secret store, then drops it from
+/// the config file
+fn migrate_plaintext_token(config_path: &Path, token: &str) -> Result<()> {
+    let location = store_secret(GITHUB_TOKEN_SECRET, token)?;
+    edit_config_file(|config| {
+        config.remove("github_token");
+    })?;
+    println!("Moved the Github token from {config_path:?} to the {location}");
+    Ok(())
 }
 
-fn get_config_path() -> Result<PathBuf> {
+pub fn get_config_path() -> Result<PathBuf> {
     let mut config_dir = config_dir().with_context(|| "Failed to determine config directory")?;
 
     config_dir.push("git-issue-flow");
@@ -51,3 +299,58 @@ fn get_config_path() -> Result<PathBuf> {
 
     Ok(config_dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(host: Option<&str>, owners: &[&str]) -> Profile {
+        Profile {
+            host: host.map(str::to_string),
+            owners: owners.iter().map(|owner| owner.to_string()).collect(),
+            ..Profile::default()
+        }
+    }
+
+    #[test]
+    fn should_select_profiles_by_remote_or_name() {
+        let mut config = Config::default();
+        config
+            .profiles
+            .insert("personal".to_string(), profile(None, &[]));
+        config
+            .profiles
+            .insert("work".to_string(), profile(None, &["acme", "acme-*"]));
+        config
+            .profiles
+            .insert("enterprise".to_string(), profile(Some("ghe.acme.com"), &[]));
+        let selected = |name, host, owner| {
+            config
+                .select_profile(name, host, owner)
+                .unwrap()
+                .map(|(name, _)| name)
+        };
+        assert_eq!(
+            selected(None, "github.com", Some("Acme-Labs")),
+            Some("work")
+        );
+        assert_eq!(
+            selected(None, "github.com", Some("alice")),
+            Some("personal")
+        );
+        assert_eq!(
+            selected(None, "ghe.acme.com", Some("acme")),
+            Some("enterprise")
+        );
+        assert_eq!(selected(None, "gitlab.com", Some("acme")), None);
+        assert_eq!(
+            selected(Some("work"), "gitlab.com", Some("alice")),
+            Some("work")
+        );
+        assert!(
+            config
+                .select_profile(Some("other"), "github.com", None)
+                .is_err()
+        );
+    }
+}
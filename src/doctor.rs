@@ -0,0 +1,422 @@
+use crate::WORKING_LABEL;
+use crate::auth::TokenSource;
+use crate::git::GitRepo;
+use crate::github::GitHubClient;
+use crate::settings::Settings;
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Pass,
+    /// Works, but something is likely to go wrong later
+    Warn,
+    Fail,
+    /// Not checked because an earlier check failed
+    Skip,
+}
+
+/// Prints each check as soon as it's done and keeps count of the failures
+#[derive(Default)]
+struct Checklist {
+    failures: usize,
+}
+
+impl Checklist {
+    fn report(&mut self, status: Status, name: &str, detail: &str, hint: Option<String>) {
+        let mark = match status {
+            Status::Pass => "✔".green(),
+            Status::Warn => "!".yellow(),
+            Status::Fail => "✘".red(),
+            Status::Skip => "-".dimmed(),
+        };
+        if status == Status::Fail {
+            self.failures += 1;
+        }
+        if detail.is_empty() {
+            println!("{mark} {name}");
+        } else {
+            println!("{mark} {name}: {}", detail.dimmed());
+        }
+        if let Some(hint) = hint {
+            println!("    {}", hint.yellow());
+        }
+    }
+    fn pass(&mut self, name: &str, detail: &str) {
+        self.report(Status::Pass, name, detail, None);
+    }
+    fn warn(&mut self, name: &str, detail: &str, hint: String) {
+        self.report(Status::Warn, name, detail, Some(hint));
+    }
+    fn fail(&mut self, name: &str, detail: &str, hint: String) {
+        self.report(Status::Fail, name, detail, Some(hint));
+    }
+    fn skip(&mut self, names: &[&str]) {
+        for name in names {
+            self.report(Status::Skip, name, "skipped", None);
+        }
+    }
+}
+
+const REPOSITORY: &str = "Git repository";
+const REMOTE: &str = "Origin remote";
+const SETTINGS: &str = "Settings";
+const TOKEN: &str = "GitHub token";
+const TOKEN_VALID: &str = "Token valid";
+const REPO_ACCESS: &str = "Repository access";
+const LABELS: &str = "Workflow labels";
+const BASE_BRANCH: &str = "Base branch";
+const PUSH: &str = "Push credentials";
+
+/// Checks everything the other commands rely on, from the repository to the push credentials,
+/// and prints a checklist with hints for whatever fails
+pub async fn doctor_command(profile: Option<&str>) -> Result<()> {
+    let mut checklist = Checklist::default();
+    let result = run_checks(&mut checklist, profile).await;
+    println!();
+    result?;
+    if checklist.failures > 0 {
+        return Err(anyhow!("{} check(s) failed", checklist.failures));
+    }
+    println!("Everything looks good");
+    Ok(())
+}
+
+async fn run_checks(checklist: &mut Checklist, profile: Option<&str>) -> Result<()> {
+    let repo = match GitRepo::open() {
+        Ok(repo) => {
+            let workdir = repo.workdir().map(|path| path.display().to_string());
+            checklist.pass(REPOSITORY, &workdir.unwrap_or_default());
+            repo
+        }
+        Err(err) => {
+            checklist.fail(
+                REPOSITORY,
+                &describe(&err),
+                "Run git-issue-flow inside a clone of a GitHub repository".to_string(),
+            );
+            checklist.skip(&[
+                REMOTE,
+                SETTINGS,
+                TOKEN,
+                TOKEN_VALID,
+                REPO_ACCESS,
+                LABELS,
+                BASE_BRANCH,
+                PUSH,
+            ]);
+            return Ok(());
+        }
+    };
+    let origin = match repo.origin() {
+        Ok(origin) => {
+            checklist.pass(
+                REMOTE,
+                &format!("{}/{}/{}", origin.host, origin.owner, origin.repo),
+            );
+            Some(origin)
+        }
+        Err(err) => {
+            checklist.fail(
+                REMOTE,
+                &describe(&err),
+                "Point origin at GitHub: git remote add origin https://github.com/OWNER/REPO.git"
+                    .to_string(),
+            );
+            None
+        }
+    };
+    let settings = match Settings::load(Some(&repo), profile) {
+        Ok(settings) => {
+            let detail = match &settings.profile {
+                Some((name, _)) => format!("profile {name}"),
+                None => String::new(),
+            };
+            checklist.pass(SETTINGS, &detail);
+            settings
+        }
+        Err(err) => {
+            checklist.fail(
+                SETTINGS,
+                &describe(&err),
+                "Run 'git-issue-flow config validate' to see what's wrong".to_string(),
+            );
+            checklist.skip(&[TOKEN, TOKEN_VALID, REPO_ACCESS, LABELS, BASE_BRANCH, PUSH]);
+            return Ok(());
+        }
+    };
+    let token = match settings.token().await {
+        Ok(Some(token)) => {
+            checklist.pass(TOKEN, &format!("from {}", token.source));
+            token
+        }
+        Ok(None) => {
+            checklist.fail(
+                TOKEN,
+                "not found",
+                "Save one with 'git-issue-flow config --token <TOKEN>', or log in with 'gh auth login'"
+                    .to_string(),
+            );
+            checklist.skip(&[TOKEN_VALID, REPO_ACCESS, LABELS]);
+            check_base_branch(checklist, &repo, &settings, None);
+            checklist.skip(&[PUSH]);
+            return Ok(());
+        }
+        Err(err) => {
+            checklist.fail(
+                TOKEN,
+                &describe(&err),
+                "Check the token_sources setting".to_string(),
+            );
+            checklist.skip(&[TOKEN_VALID, REPO_ACCESS, LABELS]);
+            check_base_branch(checklist, &repo, &settings, None);
+            checklist.skip(&[PUSH]);
+            return Ok(());
+        }
+    };
+
+    let mut default_branch = None;
+    match origin {
+        Some(origin) => {
+            let client = GitHubClient::new(
+                &token.value,
+                settings.host(),
+                origin.owner.clone(),
+                origin.repo.clone(),
+            )?;
+            // GitHub only hands out installation tokens to an app that is set up correctly, and
+            // they don't belong to a user that could be checked
+            let token_valid = if token.source == TokenSource::App {
+                checklist.pass(TOKEN_VALID, "GitHub App installation token");
+                true
+            } else {
+                check_token(checklist, &client).await
+            };
+            if token_valid {
+                default_branch =
+                    check_repository_access(checklist, &client, &origin.owner, &origin.repo).await;
+            } else {
+                checklist.skip(&[REPO_ACCESS]);
+            }
+            if default_branch.is_some() {
+                check_labels(checklist, &client, &settings, &origin.owner, &origin.repo).await;
+            } else {
+                checklist.skip(&[LABELS]);
+            }
+        }
+        None => checklist.skip(&[TOKEN_VALID, REPO_ACCESS, LABELS]),
+    }
+    check_base_branch(checklist, &repo, &settings, default_branch.as_deref());
+    match repo.check_push_access(&token.value) {
+        Ok(()) => checklist.pass(PUSH, "origin accepts them"),
+        Err(err) => {
+            let network = err
+                .downcast_ref::<git2::Error>()
+                .is_some_and(|err| err.class() == git2::ErrorClass::Net);
+            let hint = if network {
+                "Check your network connection and proxy settings"
+            } else {
+                "The token needs write access to the repository contents, or set up SSH keys for an SSH remote"
+            };
+            checklist.fail(PUSH, &describe(&err), hint.to_string());
+        }
+    }
+    Ok(())
+}
+
+async fn check_token(checklist: &mut Checklist, client: &GitHubClient) -> bool {
+    let info = match client.token_info().await {
+        Ok(info) => info,
+        Err(err) => {
+            let hint = if err.downcast_ref::<octocrab::Error>().is_some() {
+                "Check your network connection and proxy settings"
+            } else {
+                "The token was revoked or has expired, save a new one with 'git-issue-flow config --token <TOKEN>'"
+            };
+            checklist.fail(TOKEN_VALID, &describe(&err), hint.to_string());
+            return false;
+        }
+    };
+    let Some(scopes) = info.scopes else {
+        checklist.pass(TOKEN_VALID, &format!("{}, fine-grained token", info.login));
+        return true;
+    };
+    let detail = format!("{}, scopes: {}", info.login, scopes.join(", "));
+    match missing_scope(&scopes) {
+        None => checklist.pass(TOKEN_VALID, &detail),
+        Some(ScopeProblem::PublicOnly) => checklist.warn(
+            TOKEN_VALID,
+            &detail,
+            "With public_repo only, private repositories are out of reach, add the repo scope"
+                .to_string(),
+        ),
+        Some(ScopeProblem::NoRepo) => checklist.fail(
+            TOKEN_VALID,
+            &detail,
+            "Add the repo scope to the token, it's needed for issues and pull requests".to_string(),
+        ),
+    }
+    true
+}
+
+/// Checks the user can see and push to the repository, returns its default branch if so
+async fn check_repository_access(
+    checklist: &mut Checklist,
+    client: &GitHubClient,
+    owner: &str,
+    repo: &str,
+) -> Option<String> {
+    match client.repository_access().await {
+        Ok((default_branch, Some(true))) => {
+            checklist.pass(REPO_ACCESS, &format!("{owner}/{repo}, write access"));
+            Some(default_branch)
+        }
+        Ok((default_branch, None)) => {
+            checklist.pass(REPO_ACCESS, &format!("{owner}/{repo}"));
+            Some(default_branch)
+        }
+        Ok((default_branch, Some(false))) => {
+            checklist.fail(
+                REPO_ACCESS,
+                &format!("{owner}/{repo}, read only"),
+                "Ask for write access, or work from a fork you own".to_string(),
+            );
+            Some(default_branch)
+        }
+        Err(err) => {
+            checklist.fail(
+                REPO_ACCESS,
+                &describe(&err),
+                "Check the repository exists and the token may access it, fine-grained tokens list the repositories they're for"
+                    .to_string(),
+            );
+            None
+        }
+    }
+}
+
+async fn check_labels(
+    checklist: &mut Checklist,
+    client: &GitHubClient,
+    settings: &Settings,
+    owner: &str,
+    repo: &str,
+) {
+    let wanted: Vec<&str> = std::iter::once(WORKING_LABEL)
+        .chain(settings.repo.labels.iter().map(String::as_str))
+        .collect();
+    let missing = match client.missing_labels(&wanted).await {
+        Ok(missing) => missing,
+        Err(err) => {
+            checklist.fail(
+                LABELS,
+                &describe(&err),
+                "Check the token may read issues".to_string(),
+            );
+            return;
+        }
+    };
+    if missing.is_empty() {
+        checklist.pass(LABELS, &wanted.join(", "));
+    } else {
+        // GitHub creates missing labels when they're added, just without a color or description
+        checklist.warn(
+            LABELS,
+            &format!("missing {}", missing.join(", ")),
+            format!(
+                "Create them with: {}",
+                missing
+                    .iter()
+                    .map(|label| format!("gh label create {label:?} --repo {owner}/{repo}"))
+                    .collect::<Vec<_>>()
+                    .join(" && ")
+            ),
+        );
+    }
+}
+
+fn check_base_branch(
+    checklist: &mut Checklist,
+    repo: &GitRepo,
+    settings: &Settings,
+    default_branch: Option<&str>,
+) {
+    let base = settings.repo.base_branch();
+    let base_ref = repo.base_ref(base);
+    let configure = |branch: &str| {
+        format!("Set the base branch with 'git-issue-flow config set base_branch {branch} --repo'")
+    };
+    if !repo.resolves(&base_ref) {
+        let hint = match default_branch {
+            Some(default) if default != base => configure(default),
+            _ => "Fetch it with 'git fetch origin'".to_string(),
+        };
+        checklist.fail(
+            BASE_BRANCH,
+            &format!("{base} not found locally or on origin"),
+            hint,
+        );
+        return;
+    }
+    match default_branch {
+        Some(default) if default != base && settings.repo.base_branch.is_none() => checklist.warn(
+            BASE_BRANCH,
+            &format!("{base_ref}, but the default branch on GitHub is {default}"),
+            configure(default),
+        ),
+        _ => checklist.pass(BASE_BRANCH, &base_ref),
+    }
+}
+
+/// The error with its root cause, leaving out the layers in between. Those of octocrab carry a
+/// backtrace in their message, which is no use in a checklist.
+fn describe(err: &anyhow::Error) -> String {
+    let root_cause = err.root_cause().to_string();
+    let message = err.to_string();
+    if message == root_cause {
+        message
+    } else {
+        format!("{message}: {root_cause}")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ScopeProblem {
+    /// `public_repo` without `repo`
+    PublicOnly,
+    NoRepo,
+}
+
+/// What's missing from the scopes of a classic token to work with issues and pull requests
+fn missing_scope(scopes: &[String]) -> Option<ScopeProblem> {
+    if scopes.iter().any(|scope| scope == "repo") {
+        None
+    } else if scopes.iter().any(|scope| scope == "public_repo") {
+        Some(ScopeProblem::PublicOnly)
+    } else {
+        Some(ScopeProblem::NoRepo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().map(|scope| scope.to_string()).collect()
+    }
+
+    #[test]
+    fn should_find_missing_token_scopes() {
+        assert_eq!(missing_scope(&scopes(&["repo", "read:org"])), None);
+        assert_eq!(
+            missing_scope(&scopes(&["public_repo"])),
+            Some(ScopeProblem::PublicOnly)
+        );
+        assert_eq!(
+            missing_scope(&scopes(&["gist"])),
+            Some(ScopeProblem::NoRepo)
+        );
+        assert_eq!(missing_scope(&[]), Some(ScopeProblem::NoRepo));
+    }
+}
@@ -0,0 +1,101 @@
+use std::process::ExitCode;
+use thiserror::Error;
+
+/// Failures a wrapper script may want to react to, each exits with its own code. Other errors
+/// exit with 1, and 2 is left to invalid arguments.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A setting the command needs isn't configured, the message says how to set it
+    #[error("{0}")]
+    ConfigMissing(String),
+    /// GitHub refused the token
+    #[error(
+        "GitHub rejected the token ({0}), save a new one with 'config --token <TOKEN>' or 'login'"
+    )]
+    AuthFailed(String),
+    #[error("Not in a git repository, run this from inside the repository you work on")]
+    NotARepo,
+    #[error(
+        "Could not parse GitHub owner and repo from the origin URL {0}, it should look like https://github.com/<owner>/<repo>.git"
+    )]
+    RemoteUnparsable(String),
+    #[error("GitHub's API rate limit was exceeded ({0}), wait a few minutes and try again")]
+    RateLimited(String),
+    /// The work diverged from somewhere else: conflicting changes, or a branch that moved
+    #[error("{0}")]
+    Conflict(String),
+    /// The user declined to go on
+    #[error("{0}")]
+    Cancelled(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::ConfigMissing(_) => 3,
+            Error::AuthFailed(_) => 4,
+            Error::NotARepo => 5,
+            Error::RemoteUnparsable(_) => 6,
+            Error::RateLimited(_) => 7,
+            Error::Conflict(_) => 8,
+            Error::Cancelled(_) => 9,
+        }
+    }
+    /// Recognizes the API answers that have their own kind of error from the status and message
+    pub fn from_api_status(status: u16, message: &str) -> Option<Self> {
+        match status {
+            401 => Some(Error::AuthFailed(message.to_string())),
+            429 => Some(Error::RateLimited(message.to_string())),
+            // Secondary rate limits come as 403 too, only the message tells them apart
+            403 if message.to_lowercase().contains("rate limit") => {
+                Some(Error::RateLimited(message.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Exit code for an error that ended the program, from the first [`Error`] it was caused by
+pub fn exit_code(err: &anyhow::Error) -> ExitCode {
+    let code = err
+        .downcast_ref::<Error>()
+        .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<Error>()))
+        .map_or(1, Error::exit_code);
+    ExitCode::from(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn should_find_the_exit_code_behind_context() {
+        let err = Err::<(), _>(Error::Cancelled("Aborted".to_string()))
+            .context("Failed to abandon the task")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), ExitCode::from(9));
+        let err = Err::<(), _>(std::io::Error::other("disk full"))
+            .context(Error::NotARepo)
+            .unwrap_err();
+        assert_eq!(exit_code(&err), ExitCode::from(5));
+        assert_eq!(exit_code(&anyhow::anyhow!("Unknown")), ExitCode::from(1));
+    }
+
+    #[test]
+    fn should_recognize_auth_and_rate_limit_answers() {
+        assert!(matches!(
+            Error::from_api_status(401, "Bad credentials"),
+            Some(Error::AuthFailed(_))
+        ));
+        assert!(matches!(
+            Error::from_api_status(
+                403,
+                "You have exceeded a secondary rate limit. Please wait a few minutes before you try again."
+            ),
+            Some(Error::RateLimited(_))
+        ));
+        assert!(Error::from_api_status(403, "Resource not accessible by integration").is_none());
+        assert!(Error::from_api_status(404, "Not Found").is_none());
+    }
+}
@@ -1,5 +1,7 @@
+use crate::api;
+use crate::error::Error;
 use anyhow::{Context, Result, anyhow};
-use octocrab::{Octocrab, params};
+use octocrab::{AuthState, Octocrab, params};
 use serde::{Deserialize, Serialize};
 
 pub struct GitHubClient {
@@ -22,11 +24,94 @@ pub struct Label {
     pub color: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct PullRequest {
+    pub number: u64,
+    pub url: String,
+    pub title: String,
+    pub draft: bool,
+    pub merged: bool,
+    /// Commit the head branch pointed at, as GitHub last saw it
+    pub head_sha: String,
+    /// GraphQL node id, needed for mutations that the REST API doesn't offer
+    pub node_id: Option<String>,
+}
+
+impl TryFrom<octocrab::models::pulls::PullRequest> for PullRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(pr: octocrab::models::pulls::PullRequest) -> Result<Self> {
+        let url = pr
+            .html_url
+            .ok_or_else(|| anyhow!("Failed to get pull request URL"))?;
+        Ok(PullRequest {
+            number: pr.number,
+            url: url.to_string(),
+            title: pr.title.unwrap_or_default(),
+            draft: pr.draft.unwrap_or(false),
+            merged: pr.merged_at.is_some(),
+            head_sha: pr.head.sha,
+            node_id: pr.node_id,
+        })
+    }
+}
+
+/// The user a token belongs to and, for classic tokens, its OAuth scopes
+#[derive(Debug)]
+pub struct TokenInfo {
+    pub login: String,
+    /// Not reported for fine-grained tokens, whose permissions are set per repository
+    pub scopes: Option<Vec<String>>,
+}
+
+/// Reads the `X-OAuth-Scopes` header, e.g. `repo, read:org`
+fn parse_scopes(header: &str) -> Vec<String> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Turns an API failure into an [`Error`] when it's one callers can act on, e.g. a rejected token
+fn api_error(err: octocrab::Error) -> anyhow::Error {
+    if let octocrab::Error::GitHub { source, .. } = &err
+        && let Some(api_err) = Error::from_api_status(source.status_code.as_u16(), &source.message)
+    {
+        return api_err.into();
+    }
+    err.into()
+}
+
+/// Percent-encodes `value` for use as one segment of a URL path, e.g. a label name with spaces
+fn path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Extra metadata applied to a pull request after it has been created
+#[derive(Debug, Clone, Default)]
+pub struct PullRequestMetadata {
+    pub reviewers: Vec<String>,
+    pub team_reviewers: Vec<String>,
+    pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+    pub milestone: Option<u64>,
+}
+
 impl GitHubClient {
-    pub fn new(token: &str, owner: String, repo: String) -> Result<Self> {
-        let client = Octocrab::builder()
-            .personal_token(token.to_string())
-            .build()
+    /// A client for the repository `owner/repo` on `host`, github.com or a GitHub Enterprise
+    /// Server
+    pub fn new(token: &str, host: &str, owner: String, repo: String) -> Result<Self> {
+        let client = api::client(Some(token), host, AuthState::None)
             .with_context(|| "Failed to create GithubClient")?;
 
         Ok(GitHubClient {
@@ -35,6 +120,89 @@ impl GitHubClient {
             repo,
         })
     }
+    /// Login of the user the token belongs to
+    pub async fn current_user_login(&self) -> Result<String> {
+        let user = self
+            .client
+            .current()
+            .user()
+            .await
+            .map_err(api_error)
+            .with_context(|| "Failed to fetch the authenticated user")?;
+        Ok(user.login)
+    }
+    /// Checks the token against the API: who it belongs to and what it's allowed to do
+    pub async fn token_info(&self) -> Result<TokenInfo> {
+        let response = self
+            .client
+            ._get("/user")
+            .await
+            .map_err(api_error)
+            .with_context(|| "Failed to reach the GitHub API")?;
+        let status = response.status();
+        let scopes = response
+            .headers()
+            .get("x-oauth-scopes")
+            .and_then(|scopes| scopes.to_str().ok())
+            .map(parse_scopes);
+        let body = self.client.body_to_string(response).await?;
+        if !status.is_success() {
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|body| body["message"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            return Err(Error::from_api_status(status.as_u16(), &message)
+                .map_or_else(|| anyhow!("GitHub answered {status}"), anyhow::Error::from));
+        }
+        #[derive(Deserialize)]
+        struct User {
+            login: String,
+        }
+        let user: User =
+            serde_json::from_str(&body).with_context(|| "Failed to read the authenticated user")?;
+        Ok(TokenInfo {
+            login: user.login,
+            scopes,
+        })
+    }
+    /// The default branch of the repository and whether the user may push to it, which GitHub
+    /// doesn't report to GitHub Apps
+    pub async fn repository_access(&self) -> Result<(String, Option<bool>)> {
+        let repo = self
+            .client
+            .repos(&self.owner, &self.repo)
+            .get()
+            .await
+            .map_err(api_error)
+            .with_context(|| format!("Failed to fetch {}/{}", self.owner, self.repo))?;
+        let can_push = repo.permissions.map(|permissions| permissions.push);
+        Ok((repo.default_branch.unwrap_or_default(), can_push))
+    }
+    /// The labels of `names` the repository doesn't have. Each is looked up by name, which GitHub
+    /// matches case-insensitively, so it doesn't matter how many labels the repository has.
+    pub async fn missing_labels<'a>(&self, names: &[&'a str]) -> Result<Vec<&'a str>> {
+        let mut missing = Vec::new();
+        for &name in names {
+            let found = self
+                .client
+                .issues(&self.owner, &self.repo)
+                .get_label(path_segment(name))
+                .await;
+            match found {
+                Ok(_) => {}
+                Err(octocrab::Error::GitHub { source, .. })
+                    if source.status_code == http::StatusCode::NOT_FOUND =>
+                {
+                    missing.push(name)
+                }
+                Err(err) => {
+                    return Err(api_error(err))
+                        .with_context(|| format!("Failed to look up the label {name}"));
+                }
+            }
+        }
+        Ok(missing)
+    }
     pub async fn list_open_issues(&self) -> Result<Vec<Issue>> {
         let issues = self
             .client
@@ -43,6 +211,7 @@ impl GitHubClient {
             .state(params::State::Open)
             .send()
             .await
+            .map_err(api_error)
             .with_context(|| "Failed to fetch open issues")?;
         let mut result = Vec::new();
 
@@ -68,6 +237,7 @@ impl GitHubClient {
             .issues(&self.owner, &self.repo)
             .add_labels(issue_number, &[label.to_string()])
             .await
+            .map_err(api_error)
             .with_context(
                 || format!("Failed to add label {label} to issue #{issue_number}",),
             )?;
@@ -78,6 +248,7 @@ impl GitHubClient {
             .issues(&self.owner, &self.repo)
             .remove_label(issue_number, label.to_string())
             .await
+            .map_err(api_error)
             .with_context(|| {
                 format!(
                     "Failed to remove lael {label} from issue #{issue_number}"
@@ -85,24 +256,200 @@ impl GitHubClient {
             })?;
         Ok(())
     }
+    pub async fn remove_assignee(&self, issue_number: u64, login: &str) -> Result<()> {
+        self.client
+            .issues(&self.owner, &self.repo)
+            .remove_assignees(issue_number, &[login])
+            .await
+            .map_err(api_error)
+            .with_context(|| format!("Failed to unassign {login} from issue #{issue_number}"))?;
+        Ok(())
+    }
+    pub async fn comment_on_issue(&self, issue_number: u64, body: &str) -> Result<()> {
+        self.client
+            .issues(&self.owner, &self.repo)
+            .create_comment(issue_number, body)
+            .await
+            .map_err(api_error)
+            .with_context(|| format!("Failed to comment on issue #{issue_number}"))?;
+        Ok(())
+    }
     pub async fn create_pull_request(
         &self,
         title: &str,
         body: &str,
         head: &str,
         base: &str,
-    ) -> Result<String> {
+        draft: bool,
+    ) -> Result<PullRequest> {
         let pr = self
             .client
             .pulls(&self.owner, &self.repo)
             .create(title, head, base)
             .body(body)
+            .draft(draft)
             .send()
             .await
+            .map_err(api_error)
             .with_context(|| "Failed to create a pull request")?;
-        match pr.html_url {
-            Some(url) => Ok(url.to_string()),
-            None => Err(anyhow!("Failed to get pull request URL")),
+        PullRequest::try_from(pr)
+    }
+    /// Finds the open pull request whose head is the given branch of this repository
+    pub async fn find_open_pull_request(&self, head: &str) -> Result<Option<PullRequest>> {
+        let mut page = self
+            .client
+            .pulls(&self.owner, &self.repo)
+            .list()
+            .state(params::State::Open)
+            .head(format!("{}:{head}", self.owner))
+            .send()
+            .await
+            .map_err(api_error)
+            .with_context(|| format!("Failed to look up pull requests for branch {head}"))?;
+        page.take_items()
+            .into_iter()
+            .next()
+            .map(PullRequest::try_from)
+            .transpose()
+    }
+    /// Finds a merged pull request whose head is the given branch of this repository
+    pub async fn find_merged_pull_request(&self, head: &str) -> Result<Option<PullRequest>> {
+        let mut page = self
+            .client
+            .pulls(&self.owner, &self.repo)
+            .list()
+            .state(params::State::Closed)
+            .head(format!("{}:{head}", self.owner))
+            .send()
+            .await
+            .map_err(api_error)
+            .with_context(|| format!("Failed to look up pull requests for branch {head}"))?;
+        for pr in page.take_items() {
+            let pr = PullRequest::try_from(pr)?;
+            if pr.merged {
+                return Ok(Some(pr));
+            }
+        }
+        Ok(None)
+    }
+    pub async fn is_issue_closed(&self, issue_number: u64) -> Result<bool> {
+        let issue = self
+            .client
+            .issues(&self.owner, &self.repo)
+            .get(issue_number)
+            .await
+            .map_err(api_error)
+            .with_context(|| format!("Failed to fetch issue #{issue_number}"))?;
+        Ok(issue.state == octocrab::models::IssueState::Closed)
+    }
+    pub async fn update_pull_request(&self, pr_number: u64, title: &str, body: &str) -> Result<()> {
+        self.client
+            .pulls(&self.owner, &self.repo)
+            .update(pr_number)
+            .title(title)
+            .body(body)
+            .send()
+            .await
+            .map_err(api_error)
+            .with_context(|| format!("Failed to update pull request #{pr_number}"))?;
+        Ok(())
+    }
+    /// Marks a draft pull request as ready for review.
+    /// The REST API can't do it, so this goes through the GraphQL mutation.
+    pub async fn mark_pull_request_ready(&self, pr: &PullRequest) -> Result<()> {
+        let node_id = pr
+            .node_id
+            .as_deref()
+            .ok_or_else(|| anyhow!("Pull request #{} has no node id", pr.number))?;
+        let query = serde_json::json!({
+            "query": "mutation($id: ID!) { markPullRequestReadyForReview(input: {pullRequestId: $id}) { pullRequest { isDraft } } }",
+            "variables": { "id": node_id },
+        });
+        let response: serde_json::Value = self
+            .client
+            .graphql(&query)
+            .await
+            .map_err(api_error)
+            .with_context(|| format!("Failed to mark pull request #{} as ready", pr.number))?;
+        if let Some(errors) = response.get("errors") {
+            return Err(anyhow!(
+                "Failed to mark pull request #{} as ready: {errors}",
+                pr.number
+            ));
+        }
+        Ok(())
+    }
+    /// Applies reviewers, assignees, labels and milestone to a pull request.
+    /// Every step is attempted independently, the returned list holds the steps that failed.
+    pub async fn apply_pull_request_metadata(
+        &self,
+        pr_number: u64,
+        metadata: &PullRequestMetadata,
+    ) -> Vec<anyhow::Error> {
+        let mut failures = Vec::new();
+        if !metadata.reviewers.is_empty() || !metadata.team_reviewers.is_empty() {
+            let result = self
+                .client
+                .pulls(&self.owner, &self.repo)
+                .request_reviews(
+                    pr_number,
+                    metadata.reviewers.clone(),
+                    metadata.team_reviewers.clone(),
+                )
+                .await;
+            if let Err(err) = result {
+                let requested: Vec<&str> = metadata
+                    .reviewers
+                    .iter()
+                    .chain(&metadata.team_reviewers)
+                    .map(String::as_str)
+                    .collect();
+                failures.push(api_error(err).context(format!(
+                    "Failed to request reviews from {}",
+                    requested.join(", ")
+                )));
+            }
+        }
+        if !metadata.assignees.is_empty() {
+            let assignees: Vec<&str> = metadata.assignees.iter().map(String::as_str).collect();
+            let result = self
+                .client
+                .issues(&self.owner, &self.repo)
+                .add_assignees(pr_number, &assignees)
+                .await;
+            if let Err(err) = result {
+                failures.push(api_error(err).context(format!(
+                    "Failed to assign {}",
+                    metadata.assignees.join(", ")
+                )));
+            }
+        }
+        if !metadata.labels.is_empty() {
+            let result = self
+                .client
+                .issues(&self.owner, &self.repo)
+                .add_labels(pr_number, &metadata.labels)
+                .await;
+            if let Err(err) = result {
+                failures.push(api_error(err).context(format!(
+                    "Failed to add labels {}",
+                    metadata.labels.join(", ")
+                )));
+            }
+        }
+        if let Some(milestone) = metadata.milestone {
+            let result = self
+                .client
+                .issues(&self.owner, &self.repo)
+                .update(pr_number)
+                .milestone(milestone)
+                .send()
+                .await;
+            if let Err(err) = result {
+                failures
+                    .push(api_error(err).context(format!("Failed to set milestone #{milestone}")));
+            }
         }
+        failures
     }
 }
@@ -0,0 +1,158 @@
+use crate::api;
+use crate::secrets::{load_secret, store_secret};
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::EncodingKey;
+use octocrab::AuthState;
+use octocrab::auth::AppAuth;
+use octocrab::models::{AppId, InstallationToken};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// A cached token is replaced once it has less than this left, so it doesn't expire mid-command
+const EXPIRY_MARGIN: Duration = Duration::minutes(5);
+/// Installation tokens last an hour, assumed when GitHub doesn't say
+const DEFAULT_LIFETIME: Duration = Duration::hours(1);
+
+/// A GitHub App acting on one repository, from the `app_id`, `app_private_key` and
+/// `app_installation_id` settings
+#[derive(Debug)]
+pub struct AppInstallation {
+    pub app_id: u64,
+    /// PEM file with the app's private key
+    pub private_key: PathBuf,
+    /// Looked up from the repository when not set
+    pub installation_id: Option<u64>,
+    /// Host of the repository, github.com or a GitHub Enterprise Server
+    pub host: String,
+    pub owner: String,
+    pub repo: String,
+}
+
+/// An installation token saved with the secrets until it expires
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl CachedToken {
+    fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at - EXPIRY_MARGIN > now
+    }
+}
+
+impl AppInstallation {
+    /// Secret the installation token is cached under
+    fn cache_name(&self) -> String {
+        format!(
+            "app_token.{}.{}/{}/{}",
+            self.app_id, self.host, self.owner, self.repo
+        )
+    }
+    /// An installation token limited to the repository. A cached one is reused until shortly
+    /// before it expires, otherwise the app authenticates with a JWT signed by its private key
+    /// and asks GitHub for a new one.
+    pub async fn token(&self) -> Result<String> {
+        let cache_name = self.cache_name();
+        if let Some((cached, _)) = load_secret(&cache_name)?
+            && let Ok(cached) = serde_json::from_str::<CachedToken>(&cached)
+            && cached.is_fresh(Utc::now())
+        {
+            return Ok(cached.token);
+        }
+        let token = self.create_token().await?;
+        let expires_at = token
+            .expires_at
+            .as_deref()
+            .and_then(|expires_at| DateTime::parse_from_rfc3339(expires_at).ok())
+            .map_or_else(
+                || Utc::now() + DEFAULT_LIFETIME,
+                |expires_at| expires_at.to_utc(),
+            );
+        let cached = CachedToken {
+            token: token.token,
+            expires_at,
+        };
+        store_secret(&cache_name, &serde_json::to_string(&cached)?)?;
+        Ok(cached.token)
+    }
+    async fn create_token(&self) -> Result<InstallationToken> {
+        let pem = fs::read(&self.private_key).with_context(|| {
+            format!(
+                "Failed to read the app private key {}",
+                self.private_key.display()
+            )
+        })?;
+        let key = EncodingKey::from_rsa_pem(&pem).with_context(|| {
+            format!(
+                "{} is not an RSA private key in PEM format",
+                self.private_key.display()
+            )
+        })?;
+        // Requests of this client are signed with a short-lived JWT of the app
+        let app = AppAuth {
+            app_id: AppId(self.app_id),
+            key,
+        };
+        let client = api::client(None, &self.host, AuthState::App(app))
+            .with_context(|| "Failed to create GithubClient")?;
+        let installation_id = match self.installation_id {
+            Some(id) => id,
+            None => {
+                client
+                    .apps()
+                    .get_repository_installation(&self.owner, &self.repo)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "GitHub App {} is not installed on {}/{}",
+                            self.app_id, self.owner, self.repo
+                        )
+                    })?
+                    .id
+                    .0
+            }
+        };
+        let body = serde_json::json!({ "repositories": [self.repo] });
+        let token: InstallationToken = client
+            .post(
+                format!("/app/installations/{installation_id}/access_tokens"),
+                Some(&body),
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to create an installation token for {}/{}",
+                    self.owner, self.repo
+                )
+            })?;
+        if token.token.is_empty() {
+            return Err(anyhow!("GitHub returned an empty installation token"));
+        }
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_reuse_cached_tokens_until_shortly_before_expiry() {
+        let now = Utc::now();
+        let cached = |expires_in: Duration| CachedToken {
+            token: "ghs_token".to_string(),
+            expires_at: now + expires_in,
+        };
+        assert!(cached(Duration::minutes(59)).is_fresh(now));
+        assert!(!cached(Duration::minutes(2)).is_fresh(now));
+        assert!(!cached(Duration::minutes(-1)).is_fresh(now));
+
+        let saved = serde_json::to_string(&cached(Duration::minutes(30))).unwrap();
+        let loaded: CachedToken = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.token, "ghs_token");
+        assert!(loaded.is_fresh(now));
+    }
+}
@@ -1,11 +1,13 @@
 use anyhow::{Context, Result};
-use dialoguer::{Input, Select};
+use dialoguer::{Confirm, Input, Select};
 
 pub trait InputProvider {
     /// Gets a text input from the user with an optional default value
     fn get_input(&self, prompt: &str, default: Option<&str>) -> Result<String>;
     /// Gets a selection from the user from a list of options
     fn get_by_select(&self, prompt: &str, items: &[String]) -> Result<usize>;
+    /// Asks the user a yes/no question
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool>;
 }
 
 pub struct ConsoleInput;
@@ -34,14 +36,23 @@ impl InputProvider for ConsoleInput {
             .interact()
             .with_context(|| "Failed to get user input")
     }
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool> {
+        Confirm::new()
+            .with_prompt(prompt)
+            .default(default)
+            .interact()
+            .with_context(|| "Failed to get user input")
+    }
 }
 
 #[cfg(test)]
 pub struct MockInput {
     responses: Vec<String>,
     selections: Vec<usize>,
+    confirmations: Vec<bool>,
     resp_index: std::cell::Cell<usize>,
     sel_index: std::cell::Cell<usize>,
+    conf_index: std::cell::Cell<usize>,
 }
 
 #[cfg(test)]
@@ -50,10 +61,16 @@ impl MockInput {
         MockInput {
             responses,
             selections,
+            confirmations: vec![],
             resp_index: std::cell::Cell::new(0),
             sel_index: std::cell::Cell::new(0),
+            conf_index: std::cell::Cell::new(0),
         }
     }
+    pub fn with_confirmations(mut self, confirmations: Vec<bool>) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -76,6 +93,15 @@ impl InputProvider for MockInput {
             Err(anyhow::anyhow!("No more mock selections"))
         }
     }
+    fn confirm(&self, _prompt: &str, _default: bool) -> Result<bool> {
+        let index = self.conf_index.get();
+        if index < self.confirmations.len() {
+            self.conf_index.set(index + 1);
+            Ok(self.confirmations[index])
+        } else {
+            Err(anyhow::anyhow!("No more mock confirmations"))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -134,4 +160,17 @@ mod tests {
             mock.get_by_select("Select a third:", &["X".to_string(), "Y".to_string()]);
         assert!(result3.is_err());
     }
+
+    #[test]
+    fn test_mock_confirm_provider() {
+        // Create mock with predefined answers to yes/no questions
+        let mock = MockInput::new(vec![], vec![]).with_confirmations(vec![true, false]);
+
+        // Answers come back in order, regardless of the default
+        assert!(mock.confirm("Continue?", false).unwrap());
+        assert!(!mock.confirm("Really?", true).unwrap());
+
+        // Test that asking for more confirmations produces an error
+        assert!(mock.confirm("Once more?", true).is_err());
+    }
 }
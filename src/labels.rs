@@ -0,0 +1,170 @@
+use crate::git::ChangedFile;
+use crate::pattern::path_matches;
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Adds `label` to pull requests touching a path matching `pattern`, written as `src/api/** -> area:api`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "String", into = "String")]
+pub struct LabelRule {
+    pub pattern: String,
+    pub label: String,
+}
+
+impl FromStr for LabelRule {
+    type Err = anyhow::Error;
+
+    fn from_str(rule: &str) -> Result<Self> {
+        let (pattern, label) = rule
+            .split_once("->")
+            .ok_or_else(|| anyhow!("Label rule '{rule}' should look like 'pattern -> label'"))?;
+        let (pattern, label) = (pattern.trim(), label.trim());
+        if pattern.is_empty() || label.is_empty() {
+            return Err(anyhow!(
+                "Label rule '{rule}' needs both a pattern and a label"
+            ));
+        }
+        Ok(LabelRule {
+            pattern: pattern.to_string(),
+            label: label.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for LabelRule {
+    type Error = anyhow::Error;
+
+    fn try_from(rule: String) -> Result<Self> {
+        rule.parse()
+    }
+}
+
+impl From<LabelRule> for String {
+    fn from(rule: LabelRule) -> Self {
+        rule.to_string()
+    }
+}
+
+impl fmt::Display for LabelRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.pattern, self.label)
+    }
+}
+
+/// Upper bounds of changed lines for each size label, anything bigger than `l` is `XL`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct SizeThresholds {
+    pub xs: usize,
+    pub s: usize,
+    pub m: usize,
+    pub l: usize,
+    /// Prepended to the size, e.g. `size/M`
+    pub prefix: String,
+}
+
+impl Default for SizeThresholds {
+    fn default() -> Self {
+        SizeThresholds {
+            xs: 10,
+            s: 100,
+            m: 500,
+            l: 1000,
+            prefix: "size/".to_string(),
+        }
+    }
+}
+
+impl SizeThresholds {
+    pub fn label_for(&self, changed_lines: usize) -> String {
+        let size = match changed_lines {
+            n if n <= self.xs => "XS",
+            n if n <= self.s => "S",
+            n if n <= self.m => "M",
+            n if n <= self.l => "L",
+            _ => "XL",
+        };
+        format!("{}{size}", self.prefix)
+    }
+}
+
+/// Labels for a change: every rule matching one of the files, then the size label
+pub fn labels_for_changes(
+    rules: &[LabelRule],
+    sizes: Option<&SizeThresholds>,
+    files: &[ChangedFile],
+) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+    for rule in rules {
+        let matched = files
+            .iter()
+            .any(|file| file.paths().any(|path| path_matches(&rule.pattern, path)));
+        if matched && !labels.contains(&rule.label) {
+            labels.push(rule.label.clone());
+        }
+    }
+    if let Some(sizes) = sizes {
+        let changed_lines = files
+            .iter()
+            .map(|file| file.insertions + file.deletions)
+            .sum();
+        labels.push(sizes.label_for(changed_lines));
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(path: &str, insertions: usize, deletions: usize) -> ChangedFile {
+        ChangedFile {
+            path: path.to_string(),
+            old_path: None,
+            insertions,
+            deletions,
+        }
+    }
+
+    #[test]
+    fn should_parse_label_rules() {
+        let rule: LabelRule = "src/api/** -> area:api".parse().unwrap();
+        assert_eq!(rule.pattern, "src/api/**");
+        assert_eq!(rule.label, "area:api");
+        assert_eq!(rule.to_string(), "src/api/** -> area:api");
+        assert!("src/api/**".parse::<LabelRule>().is_err());
+        assert!("src/api/** -> ".parse::<LabelRule>().is_err());
+    }
+
+    #[test]
+    fn should_pick_size_by_changed_lines() {
+        let sizes = SizeThresholds::default();
+        assert_eq!(sizes.label_for(0), "size/XS");
+        assert_eq!(sizes.label_for(10), "size/XS");
+        assert_eq!(sizes.label_for(11), "size/S");
+        assert_eq!(sizes.label_for(500), "size/M");
+        assert_eq!(sizes.label_for(1000), "size/L");
+        assert_eq!(sizes.label_for(1001), "size/XL");
+    }
+
+    #[test]
+    fn should_label_matching_paths_and_size() {
+        let rules = vec![
+            "src/api/** -> area:api".parse().unwrap(),
+            "*.md -> docs".parse().unwrap(),
+            "src/web/** -> area:web".parse().unwrap(),
+        ];
+        let files = vec![
+            changed("src/api/users.rs", 400, 10),
+            changed("README.md", 5, 0),
+            changed("src/api/orders.rs", 30, 20),
+        ];
+        let labels = labels_for_changes(&rules, Some(&SizeThresholds::default()), &files);
+        assert_eq!(labels, vec!["area:api", "docs", "size/M"]);
+        let labels = labels_for_changes(&rules, None, &files);
+        assert_eq!(labels, vec!["area:api", "docs"]);
+    }
+}
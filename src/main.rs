@@ -1,114 +1,577 @@
+mod api;
+mod auth;
+mod checks;
 mod cli;
+mod codeowners;
 mod config;
+mod doctor;
+mod error;
 mod git;
 mod github;
+mod github_app;
 mod inputs;
+mod labels;
+mod oauth;
+mod pattern;
+mod secrets;
+mod settings;
+mod state;
+#[cfg(test)]
+mod test_server;
 mod utils;
 
-use anyhow::Result;
-use cli::{Commands, parse_args};
+use anyhow::{Context, Result};
+use auth::TokenSource;
+use checks::{markdown_report, print_summary, run_checks, shell};
+use cli::{Commands, ConfigAction, ProfileAction, parse_args};
+use codeowners::{CodeOwners, Reviewers};
 use colored::Colorize;
-use config::Config;
-use git::GitRepo;
-use github::GitHubClient;
+use config::{Config, Profile, SyncStrategy, profile_token_secret};
+use doctor::doctor_command;
+use error::{Error, exit_code};
+use git::{ChangedFile, GitRepo, SyncOutcome};
+use github::{GitHubClient, Issue, PullRequest, PullRequestMetadata};
 use inputs::{ConsoleInput, InputProvider};
-use utils::{create_branch_name_from_issue, create_pr_text, select_issue};
+use labels::labels_for_changes;
+use oauth::{DEFAULT_SCOPES, DeviceFlow};
+use secrets::{GITHUB_TOKEN_SECRET, load_secret, remove_secret};
+use serde_json::Value;
+use settings::{
+    Origin, REPO_CONFIG_FILE, Scope, Settings, TOKEN_KEY, check_key, json_schema, set_setting,
+    unset_setting, validate_scope,
+};
+use state::TaskState;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use utils::{
+    create_branch_name_from_issue, create_pr_text, expand_worktree_template,
+    issue_number_from_branch, merge_unique, normalize_path, select_issue,
+};
 const WORKING_LABEL: &str = "working-on";
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            exit_code(&err)
+        }
+    }
+}
+
+async fn run() -> Result<()> {
     let args = parse_args();
+    api::set_verbose(args.verbose);
+    let profile = args.profile.as_deref();
     match args.command {
-        Commands::Config { token } => {
-            println!("Configuring with token: {token:?}");
-            let mut config = Config::load()?;
+        Commands::Config {
+            action: Some(action),
+            ..
+        } => config_command(profile, action).await,
+        Commands::Config {
+            token,
+            action: None,
+        } => {
+            // Loading moves a token saved in plaintext by older versions to the secret store
+            Config::load()?;
             if let Some(token) = token {
-                config.set_github_token(token)?;
-                println!("Github token saved successfully!");
-            } else if let Some(token) = &config.github_token {
-                println!("Github token already set: {}****", &token[0..4]);
+                let location = Config::set_github_token(&token)?;
+                println!("Github token saved in the {location}");
+            } else if let Some((_, location)) = load_secret(GITHUB_TOKEN_SECRET)? {
+                println!("Github token is set, stored in the {location}");
             } else {
                 println!("Github token not set");
             }
             Ok(())
         }
+        Commands::Status => status_command(profile),
+        Commands::Doctor => doctor_command(profile).await,
+        Commands::Login { client_id, scopes } => login_command(profile, client_id, scopes).await,
+        Commands::Abandon {
+            issue,
+            comment,
+            delete_remote,
+        } => {
+            let input_provider = ConsoleInput;
+            abandon_command(profile, &input_provider, issue, comment, delete_remote).await?;
+            Ok(())
+        }
+        Commands::Sync {
+            rebase,
+            merge,
+            push,
+        } => {
+            let strategy = match (rebase, merge) {
+                (true, _) => Some(SyncStrategy::Rebase),
+                (_, true) => Some(SyncStrategy::Merge),
+                _ => None,
+            };
+            sync_command(profile, strategy, push).await?;
+            Ok(())
+        }
+        Commands::Cleanup { dry_run } => {
+            cleanup_command(profile, dry_run).await?;
+            Ok(())
+        }
+        Commands::Switch => {
+            let input_provider = ConsoleInput;
+            switch_command(profile, &input_provider).await?;
+            Ok(())
+        }
         Commands::List => {
             println!("Listing tasks");
-            list_command().await?;
+            list_command(profile).await?;
             Ok(())
         }
-        Commands::Start => {
+        Commands::Start {
+            draft_pr,
+            from,
+            worktree,
+        } => {
             let input_provider = ConsoleInput;
-            start_command(&input_provider).await?;
+            start_command(profile, &input_provider, draft_pr, from, worktree).await?;
             Ok(())
         }
-        Commands::Finish { title, description } => {
+        Commands::Finish {
+            title,
+            description,
+            draft,
+            ready,
+            reviewers,
+            team_reviewers,
+            assignees,
+            labels,
+            milestone,
+            no_auto_reviewers,
+            skip_checks,
+        } => {
             let input_provider = ConsoleInput;
-            finish_command(&input_provider, title, description).await?;
+            let metadata = PullRequestMetadata {
+                reviewers,
+                team_reviewers,
+                assignees,
+                labels,
+                milestone,
+            };
+            let text = PrText {
+                title,
+                description,
+                checks_report: None,
+            };
+            // Either flag wins over the draft setting
+            let draft = match (draft, ready) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            };
+            finish_command(
+                profile,
+                &input_provider,
+                text,
+                draft,
+                metadata,
+                !no_auto_reviewers,
+                !skip_checks,
+            )
+            .await?;
             Ok(())
         }
     }
 }
 
-async fn start_command(input_provider: &dyn InputProvider) -> Result<()> {
-    let config = Config::load()?;
-    let token = config.github_token.ok_or_else(|| {
-        anyhow::anyhow!("Github token not found. Please set it with config --token <TOKEN>")
-    })?;
-    let repo = GitRepo::open()?;
+async fn start_command(
+    profile: Option<&str>,
+    input_provider: &dyn InputProvider,
+    draft_pr: bool,
+    from: Option<String>,
+    worktree: bool,
+) -> Result<()> {
+    let mut repo = GitRepo::open()?;
+    let settings = Settings::load(Some(&repo), profile)?;
+    let token = settings.github_token().await?;
     let (owner, repo_name) = repo.get_repo_owner_and_name()?;
+    let repo_config = &settings.repo;
+    let base = repo_config.base_branch();
+    if let Some((name, profile)) = &settings.profile
+        && repo.set_identity(profile.user_name.as_deref(), profile.user_email.as_deref())?
+    {
+        println!("Commits in this repository will be authored as set in the {name} profile");
+    }
 
     println!("Fetching issues from {owner} - {repo_name} ! ");
-    let client = GitHubClient::new(&token, owner, repo_name)?;
+    let client = GitHubClient::new(&token, settings.host(), owner, repo_name.clone())?;
     let issues = client.list_open_issues().await?;
     let selected = select_issue(&issues, "Select an issue to work on", input_provider)?;
     println!("Starting task:#{} {}", selected.number, selected.title);
+    let branch_name = create_branch_name_from_issue(selected, repo_config.branch_template());
+    println!("Fetching origin...");
+    if let Err(err) = repo.fetch(&token) {
+        println!(
+            "{}",
+            format!("{err:#}, starting from what is available locally").yellow()
+        );
+    }
+    let start_point = from.unwrap_or_else(|| repo.base_ref(base));
+    let (task_repo, worktree_path) = if worktree {
+        let template = repo_config.worktree_dir();
+        let path = normalize_path(&repo.main_workdir()?.join(expand_worktree_template(
+            template,
+            &repo_name,
+            selected.number,
+        )));
+        let path = prepare_task_worktree(&repo, input_provider, &branch_name, &start_point, path)?;
+        (GitRepo::open_at(&path)?, Some(path))
+    } else {
+        switch_to_task_branch(&mut repo, input_provider, &branch_name, &start_point)?;
+        (repo, None)
+    };
+    let mut state = TaskState::load(task_repo.common_dir())?;
+    state.task_mut(&branch_name, selected.number).worktree = worktree_path.clone();
+    state.save()?;
     client
         .add_label_to_issue(selected.number, WORKING_LABEL)
         .await?;
-    let branch_name = create_branch_name_from_issue(selected);
-    repo.create_branch(&branch_name)?;
-    println!("Created and switched to branch {branch_name}");
 
-    println!("\nYou're all set! Make your changes and when you're ready to create a PR, run:");
+    if draft_pr {
+        open_draft_pull_request(&task_repo, &client, selected, &branch_name, base, &token).await?;
+        println!("\nYou're all set! Make your changes and when you're ready for review, run:");
+    } else {
+        println!("\nYou're all set! Make your changes and when you're ready to create a PR, run:");
+    }
+    if let Some(path) = worktree_path {
+        println!("  cd {}", path.display());
+    }
     println!("  git-issue-flow finish");
     Ok(())
 }
 
+/// Switches the current worktree to the task branch, resuming it if it already exists
+fn switch_to_task_branch(
+    repo: &mut GitRepo,
+    input_provider: &dyn InputProvider,
+    branch_name: &str,
+    start_point: &str,
+) -> Result<()> {
+    if repo.get_current_branch_name().ok().as_deref() == Some(branch_name) {
+        println!("Already on branch {branch_name}");
+    } else if repo.local_branch_exists(branch_name) {
+        if !input_provider.confirm(
+            &format!("Branch {branch_name} already exists, check it out?"),
+            true,
+        )? {
+            return Err(
+                Error::Cancelled(format!("Aborted, branch {branch_name} already exists")).into(),
+            );
+        }
+        handle_dirty_tree(repo, input_provider, branch_name)?;
+        repo.checkout_branch(branch_name)?;
+        println!("Switched to existing branch {branch_name}");
+    } else if repo.remote_branch_exists(branch_name) {
+        if !input_provider.confirm(
+            &format!("Branch {branch_name} exists on origin, check it out?"),
+            true,
+        )? {
+            return Err(Error::Cancelled(format!(
+                "Aborted, branch {branch_name} already exists on origin"
+            ))
+            .into());
+        }
+        handle_dirty_tree(repo, input_provider, branch_name)?;
+        repo.create_branch(branch_name, &format!("origin/{branch_name}"))?;
+        println!("Created branch {branch_name} tracking origin/{branch_name}");
+    } else {
+        handle_dirty_tree(repo, input_provider, branch_name)?;
+        repo.create_branch(branch_name, start_point)?;
+        println!("Created and switched to branch {branch_name} from {start_point}");
+    }
+    Ok(())
+}
+
+/// Makes sure the task branch is checked out in a linked worktree, returns the worktree path.
+/// The current worktree is left alone, so uncommitted changes in it don't matter.
+fn prepare_task_worktree(
+    repo: &GitRepo,
+    input_provider: &dyn InputProvider,
+    branch_name: &str,
+    start_point: &str,
+    path: PathBuf,
+) -> Result<PathBuf> {
+    let existing = repo
+        .worktrees()?
+        .into_iter()
+        .find(|worktree| worktree.branch.as_deref() == Some(branch_name));
+    if let Some(existing) = existing {
+        println!(
+            "Branch {branch_name} is already checked out in {}",
+            existing.path.display()
+        );
+        return Ok(existing.path);
+    }
+    if repo.local_branch_exists(branch_name) {
+        println!("Using existing branch {branch_name}");
+    } else if repo.remote_branch_exists(branch_name) {
+        if !input_provider.confirm(
+            &format!("Branch {branch_name} exists on origin, use it?"),
+            true,
+        )? {
+            return Err(Error::Cancelled(format!(
+                "Aborted, branch {branch_name} already exists on origin"
+            ))
+            .into());
+        }
+        repo.create_branch_at(branch_name, &format!("origin/{branch_name}"))?;
+        println!("Created branch {branch_name} tracking origin/{branch_name}");
+    } else {
+        repo.create_branch_at(branch_name, start_point)?;
+        println!("Created branch {branch_name} from {start_point}");
+    }
+    repo.add_worktree(branch_name, &path)?;
+    println!("Created worktree {}", path.display());
+    Ok(path)
+}
+
+enum DirtyTreeAction {
+    CarryOver,
+    Stash,
+    Abort,
+}
+
+impl DirtyTreeAction {
+    fn label(&self) -> &'static str {
+        match self {
+            DirtyTreeAction::CarryOver => "Carry them over",
+            DirtyTreeAction::Stash => "Stash them",
+            DirtyTreeAction::Abort => "Abort",
+        }
+    }
+}
+
+/// Asks what to do with uncommitted changes before switching to another branch
+fn handle_dirty_tree(
+    repo: &mut GitRepo,
+    input_provider: &dyn InputProvider,
+    target_branch: &str,
+) -> Result<()> {
+    if !repo.is_dirty()? {
+        return Ok(());
+    }
+    println!(
+        "{}",
+        "You have uncommitted changes in your working tree.".yellow()
+    );
+    let actions = [
+        DirtyTreeAction::CarryOver,
+        DirtyTreeAction::Stash,
+        DirtyTreeAction::Abort,
+    ];
+    let items: Vec<String> = actions.iter().map(|a| a.label().to_string()).collect();
+    let selection = input_provider.get_by_select("What do you want to do with them?", &items)?;
+    match actions[selection] {
+        DirtyTreeAction::CarryOver => Ok(()),
+        DirtyTreeAction::Stash => {
+            repo.stash_changes(&format!(
+                "git-issue-flow: before switching to {target_branch}"
+            ))?;
+            println!("Changes stashed, bring them back with 'git stash pop'");
+            Ok(())
+        }
+        DirtyTreeAction::Abort => Err(Error::Cancelled(
+            "Aborted, commit or stash your changes and try again".to_string(),
+        )
+        .into()),
+    }
+}
+
+/// Pushes the freshly created task branch and opens a draft PR for it, remembering it for `finish`
+async fn open_draft_pull_request(
+    repo: &GitRepo,
+    client: &GitHubClient,
+    issue: &Issue,
+    branch_name: &str,
+    base: &str,
+    token: &str,
+) -> Result<()> {
+    if let Some(existing) = client.find_open_pull_request(branch_name).await? {
+        println!("Pull request already open: {}", existing.url.blue());
+        return Ok(());
+    }
+    // GitHub refuses pull requests without any commits, so give the branch an initial one
+    if repo.commits_ahead_of(base)? == 0 {
+        repo.create_empty_commit(&format!("Start work on #{}: {}", issue.number, issue.title))?;
+    }
+    repo.push_branch(branch_name, token)?;
+    let pr = client
+        .create_pull_request(
+            &issue.title,
+            &create_pr_text(issue.number, ""),
+            branch_name,
+            base,
+            true,
+        )
+        .await?;
+    let mut state = TaskState::load(repo.common_dir())?;
+    state.task_mut(branch_name, issue.number).draft_pr = Some(pr.number);
+    state.save()?;
+    println!("Draft pull request opened: {}", pr.url.blue());
+    Ok(())
+}
+
 async fn finish_command(
+    profile: Option<&str>,
     input_provider: &dyn InputProvider,
-    title: Option<String>,
-    desc: Option<String>,
+    mut text: PrText,
+    draft: Option<bool>,
+    metadata: PullRequestMetadata,
+    auto_reviewers: bool,
+    run_configured_checks: bool,
 ) -> Result<()> {
-    let config = Config::load()?;
-    let token = config.github_token.ok_or_else(|| {
-        anyhow::anyhow!("Github token not found!. Please set it up with 'config --token <TOKEN>'")
-    })?;
     let repo = GitRepo::open()?;
+    let settings = Settings::load(Some(&repo), profile)?;
+    let token = settings.github_token().await?;
     let (owner, repo_name) = repo.get_repo_owner_and_name()?;
+    let repo_config = &settings.repo;
     let current_branch = repo.get_current_branch_name()?;
-    let issue_number = current_branch
-        .strip_prefix("feature/")
-        .and_then(|num| num.parse::<u64>().ok())
-        .ok_or_else(|| {
-            anyhow::anyhow!(
+    let template = repo_config.branch_template();
+    let Some(issue_number) = issue_number_from_branch(&current_branch, template) else {
+        let task_worktrees: Vec<String> = repo
+            .worktrees()?
+            .into_iter()
+            .filter(|worktree| {
+                worktree
+                    .branch
+                    .as_deref()
+                    .and_then(|branch| issue_number_from_branch(branch, template))
+                    .is_some()
+            })
+            .map(|worktree| format!("  {}", normalize_path(&worktree.path).display()))
+            .collect();
+        if task_worktrees.is_empty() {
+            return Err(anyhow::anyhow!(
                 "Current branch {} is not a valid issue branch",
                 current_branch
-            )
-        })?;
-    let client = GitHubClient::new(&token, owner, repo_name)?;
-    let title = match title {
-        Some(t) => t,
-        None => input_provider.get_input("Wprowadź tytuł dla PR", None)?,
+            ));
+        }
+        return Err(anyhow::anyhow!(
+            "Current branch {} is not a valid issue branch, run finish from one of the task worktrees:\n{}",
+            current_branch,
+            task_worktrees.join("\n")
+        ));
     };
-    let description = match desc {
-        Some(d) => d,
-        None => input_provider.get_input("Wprowadź opis dla PR", None)?,
+    let mut metadata = PullRequestMetadata {
+        reviewers: merge_unique(&repo_config.reviewers, &metadata.reviewers),
+        team_reviewers: merge_unique(&repo_config.team_reviewers, &metadata.team_reviewers),
+        assignees: merge_unique(&repo_config.assignees, &metadata.assignees),
+        labels: merge_unique(&repo_config.labels, &metadata.labels),
+        milestone: metadata.milestone.or(repo_config.milestone),
     };
-    let pr_body = create_pr_text(issue_number, &description);
-    let pr_url = client
-        .create_pull_request(&title, &pr_body, &current_branch, "main")
+    let draft = draft.unwrap_or(repo_config.draft);
+    let base = repo_config.base_branch();
+    let client = GitHubClient::new(&token, settings.host(), owner, repo_name)?;
+    let existing = client.find_open_pull_request(&current_branch).await?;
+    // The merge check is for opening a pull request, finish run again only updates it
+    if existing.is_none() {
+        // The base may have moved on since the branch was started, check against where it is now
+        if let Err(err) = repo.fetch(&token) {
+            println!(
+                "{}",
+                format!("{err:#}, checking against what is available locally").yellow()
+            );
+        }
+        check_branch_can_be_merged(&repo, &current_branch, base)?;
+    }
+    if run_configured_checks && !repo_config.checks.is_empty() {
+        let results = run_checks(&repo_config.checks, repo.workdir()?)?;
+        print_summary(&results);
+        let failed = results.iter().filter(|result| !result.passed).count();
+        if failed > 0 {
+            return Err(anyhow::anyhow!(
+                "{} check(s) failed, fix them or run finish with --skip-checks",
+                failed
+            ));
+        }
+        if repo_config.checks_in_pr_body {
+            text.checks_report = Some(markdown_report(&results));
+        }
+    }
+    repo.push_branch(&current_branch, &token)?;
+    println!("Pushed {current_branch} to origin");
+    match repo.changed_files(base) {
+        Ok(changed_files) => {
+            let auto_labels = labels_for_changes(
+                &repo_config.label_rules,
+                repo_config.size_labels.as_ref(),
+                &changed_files,
+            );
+            metadata.labels = merge_unique(&metadata.labels, &auto_labels);
+            if auto_reviewers {
+                match codeowner_reviewers(&repo, &client, &changed_files).await {
+                    Ok(owners) => {
+                        metadata.reviewers = merge_unique(&metadata.reviewers, &owners.users);
+                        metadata.team_reviewers =
+                            merge_unique(&metadata.team_reviewers, &owners.teams);
+                    }
+                    Err(err) => println!(
+                        "{}",
+                        format!("Skipping reviewers from CODEOWNERS: {err:#}").yellow()
+                    ),
+                }
+            }
+        }
+        Err(err) => println!(
+            "{}",
+            format!("Skipping automatic labels and reviewers: {err:#}").yellow()
+        ),
+    }
+    if let Some(existing) = existing {
+        let mut state = TaskState::load(repo.common_dir())?;
+        let opened_by_start = state
+            .task(&current_branch)
+            .and_then(|task| task.draft_pr)
+            .is_some_and(|number| number == existing.number);
+        if opened_by_start && existing.draft {
+            let (title, pr_body) =
+                ask_for_pr_text(input_provider, issue_number, text, Some(&existing.title))?;
+            client
+                .update_pull_request(existing.number, &title, &pr_body)
+                .await?;
+            client.mark_pull_request_ready(&existing).await?;
+            println!("Pull request is ready for review: {}", existing.url.blue());
+            let failures = client
+                .apply_pull_request_metadata(existing.number, &metadata)
+                .await;
+            report_metadata_failures(&failures);
+            client
+                .remove_label_from_issue(issue_number, WORKING_LABEL)
+                .await?;
+            state.task_mut(&current_branch, issue_number).draft_pr = None;
+            state.save()?;
+            return Ok(());
+        }
+        return update_existing_pull_request(
+            input_provider,
+            &client,
+            existing,
+            issue_number,
+            text,
+            &metadata,
+        )
+        .await;
+    }
+    let (title, pr_body) = ask_for_pr_text(input_provider, issue_number, text, None)?;
+    let pr = client
+        .create_pull_request(&title, &pr_body, &current_branch, base, draft)
         .await?;
-    println!("Pull request created: {}", pr_url.blue());
+    if pr.draft {
+        println!("Draft pull request created: {}", pr.url.blue());
+    } else {
+        println!("Pull request created: {}", pr.url.blue());
+    }
+
+    let failures = client
+        .apply_pull_request_metadata(pr.number, &metadata)
+        .await;
+    report_metadata_failures(&failures);
 
     client
         .remove_label_from_issue(issue_number, WORKING_LABEL)
@@ -117,15 +580,967 @@ async fn finish_command(
     Ok(())
 }
 
-async fn list_command() -> Result<()> {
-    let config = Config::load()?;
-    let token = config.github_token.ok_or_else(|| {
-        anyhow::anyhow!("Github token not found!. Please set it up with 'config --token <TOKEN>'")
-    })?;
+/// Refuses a branch without commits of its own, GitHub won't open a pull request for it anyway,
+/// and warns when merging it into the base would conflict
+fn check_branch_can_be_merged(repo: &GitRepo, branch: &str, base: &str) -> Result<()> {
+    if repo.commits_ahead_of(base)? == 0 {
+        return Err(anyhow::anyhow!(
+            "Branch {} has no commits that aren't already in {}, commit your work first",
+            branch,
+            base
+        ));
+    }
+    match repo.conflicts_with(base) {
+        Ok(conflicts) if conflicts.is_empty() => {}
+        Ok(conflicts) => {
+            println!("{}", format!("{branch} conflicts with {base} in:").yellow());
+            for path in conflicts {
+                println!("  {path}");
+            }
+            println!(
+                "Run `git-issue-flow sync` to resolve them before the pull request gets reviewed"
+            );
+        }
+        Err(err) => println!(
+            "{}",
+            format!("Skipping the conflict check: {err:#}").yellow()
+        ),
+    }
+    Ok(())
+}
+
+/// Reviewers from CODEOWNERS for the files changed on the branch, without the PR author
+async fn codeowner_reviewers(
+    repo: &GitRepo,
+    client: &GitHubClient,
+    changed_files: &[ChangedFile],
+) -> Result<Reviewers> {
+    let Some(codeowners) = CodeOwners::find(repo.workdir()?)? else {
+        return Ok(Reviewers::default());
+    };
+    let author = client.current_user_login().await?;
+    let reviewers =
+        codeowners.reviewers_for(changed_files.iter().flat_map(ChangedFile::paths), &author);
+    if !reviewers.users.is_empty() || !reviewers.teams.is_empty() {
+        let owners: Vec<&str> = reviewers
+            .users
+            .iter()
+            .chain(&reviewers.teams)
+            .map(String::as_str)
+            .collect();
+        println!("Code owners of the changed files: {}", owners.join(", "));
+    }
+    Ok(reviewers)
+}
+
+enum ExistingPrAction {
+    Update,
+    MarkReady,
+    ShowUrl,
+}
+
+impl ExistingPrAction {
+    fn label(&self) -> &'static str {
+        match self {
+            ExistingPrAction::Update => "Update title and description",
+            ExistingPrAction::MarkReady => "Mark as ready for review",
+            ExistingPrAction::ShowUrl => "Just show the URL",
+        }
+    }
+}
+
+async fn update_existing_pull_request(
+    input_provider: &dyn InputProvider,
+    client: &GitHubClient,
+    pr: PullRequest,
+    issue_number: u64,
+    text: PrText,
+    metadata: &PullRequestMetadata,
+) -> Result<()> {
+    println!(
+        "Pull request #{} already exists for this branch: {}",
+        pr.number,
+        pr.url.blue()
+    );
+    let mut actions = vec![ExistingPrAction::Update];
+    if pr.draft {
+        actions.push(ExistingPrAction::MarkReady);
+    }
+    actions.push(ExistingPrAction::ShowUrl);
+    let items: Vec<String> = actions.iter().map(|a| a.label().to_string()).collect();
+    let selection = input_provider.get_by_select("What do you want to do?", &items)?;
+    match actions[selection] {
+        ExistingPrAction::Update => {
+            let (title, pr_body) =
+                ask_for_pr_text(input_provider, issue_number, text, Some(&pr.title))?;
+            client
+                .update_pull_request(pr.number, &title, &pr_body)
+                .await?;
+            println!("Pull request updated: {}", pr.url.blue());
+            let failures = client
+                .apply_pull_request_metadata(pr.number, metadata)
+                .await;
+            report_metadata_failures(&failures);
+        }
+        ExistingPrAction::MarkReady => {
+            client.mark_pull_request_ready(&pr).await?;
+            println!("Pull request marked as ready for review: {}", pr.url.blue());
+            let failures = client
+                .apply_pull_request_metadata(pr.number, metadata)
+                .await;
+            report_metadata_failures(&failures);
+            client
+                .remove_label_from_issue(issue_number, WORKING_LABEL)
+                .await?;
+        }
+        ExistingPrAction::ShowUrl => println!("{}", pr.url.blue()),
+    }
+    Ok(())
+}
+
+/// Pull request text given on the command line, plus the sections `finish` generates
+struct PrText {
+    title: Option<String>,
+    description: Option<String>,
+    checks_report: Option<String>,
+}
+
+/// Asks for the PR title and description unless they were given, returns the title and the PR body
+fn ask_for_pr_text(
+    input_provider: &dyn InputProvider,
+    issue_number: u64,
+    text: PrText,
+    default_title: Option<&str>,
+) -> Result<(String, String)> {
+    let title = match text.title {
+        Some(t) => t,
+        None => input_provider.get_input("Wprowadź tytuł dla PR", default_title)?,
+    };
+    let description = match text.description {
+        Some(d) => d,
+        None => input_provider.get_input("Wprowadź opis dla PR", None)?,
+    };
+    let mut body = create_pr_text(issue_number, &description);
+    if let Some(report) = text.checks_report {
+        body.push_str("\n\n");
+        body.push_str(&report);
+    }
+    Ok((title, body))
+}
+
+fn report_metadata_failures(failures: &[anyhow::Error]) {
+    if failures.is_empty() {
+        return;
+    }
+    println!(
+        "{}",
+        format!(
+            "Pull request is in place, but {} metadata update(s) failed:",
+            failures.len()
+        )
+        .yellow()
+    );
+    for failure in failures {
+        println!("  - {failure:#}");
+    }
+}
+
+/// Local branches that belong to a task, with their issue number, ordered by issue
+fn task_branches(repo: &GitRepo, state: &TaskState, template: &str) -> Result<Vec<(String, u64)>> {
+    let mut tasks: Vec<(String, u64)> = repo
+        .local_branches()?
+        .into_iter()
+        .filter_map(|branch| {
+            let issue = state
+                .task(&branch)
+                .map(|task| task.issue)
+                .or_else(|| issue_number_from_branch(&branch, template))?;
+            Some((branch, issue))
+        })
+        .collect();
+    tasks.sort_by_key(|(_, issue)| *issue);
+    Ok(tasks)
+}
+
+/// Checks out another task branch, showing the issue titles to pick from
+async fn switch_command(profile: Option<&str>, input_provider: &dyn InputProvider) -> Result<()> {
+    let mut repo = GitRepo::open()?;
+    let settings = Settings::load(Some(&repo), profile)?;
+    let state = TaskState::load(repo.common_dir())?;
+    let tasks = task_branches(&repo, &state, settings.repo.branch_template())?;
+    if tasks.is_empty() {
+        return Err(anyhow::anyhow!("No task branches to switch to"));
+    }
+    let titles = match issue_titles(&settings, &repo).await {
+        Ok(titles) => titles,
+        Err(err) => {
+            println!(
+                "{}",
+                format!("Couldn't fetch issue titles: {err:#}").yellow()
+            );
+            HashMap::new()
+        }
+    };
+    let current = repo.get_current_branch_name().ok();
+    let items: Vec<String> = tasks
+        .iter()
+        .map(|(branch, issue)| {
+            let title = titles.get(issue).map(String::as_str).unwrap_or_default();
+            let marker = if current.as_deref() == Some(branch.as_str()) {
+                " (current)"
+            } else {
+                ""
+            };
+            format!(
+                "#{} {} {}{}",
+                issue.to_string().blue(),
+                title,
+                branch.dimmed(),
+                marker.green()
+            )
+        })
+        .collect();
+    let selection = input_provider.get_by_select("Select a task to switch to", &items)?;
+    let (branch, issue) = &tasks[selection];
+    if current.as_deref() == Some(branch.as_str()) {
+        println!("Already on branch {branch}");
+        return Ok(());
+    }
+    // A branch checked out in another worktree can't be checked out here as well
+    let current_worktree = repo.workdir().ok().map(normalize_path);
+    let other_worktree = repo.worktrees()?.into_iter().find(|worktree| {
+        worktree.branch.as_deref() == Some(branch.as_str())
+            && Some(normalize_path(&worktree.path)) != current_worktree
+    });
+    if let Some(worktree) = other_worktree {
+        println!("Task #{issue} is checked out in another worktree, go there with:");
+        println!("  cd {}", normalize_path(&worktree.path).display());
+        return Ok(());
+    }
+    handle_dirty_tree(&mut repo, input_provider, branch)?;
+    repo.checkout_branch(branch)?;
+    println!("Switched to branch {branch} for task #{issue}");
+    Ok(())
+}
+
+/// Undoes `start`: frees the issue, switches back to the base branch and deletes the task branch
+async fn abandon_command(
+    profile: Option<&str>,
+    input_provider: &dyn InputProvider,
+    issue: Option<u64>,
+    comment: Option<String>,
+    delete_remote: bool,
+) -> Result<()> {
+    let mut repo = GitRepo::open()?;
+    let settings = Settings::load(Some(&repo), profile)?;
+    let token = settings.github_token().await?;
+    let (owner, repo_name) = repo.get_repo_owner_and_name()?;
+    let repo_config = &settings.repo;
+    let base = repo_config.base_branch();
+    let mut state = TaskState::load(repo.common_dir())?;
+    let current = repo.get_current_branch_name().ok();
+
+    let (branch, issue_number) = match issue {
+        Some(number) => task_branches(&repo, &state, repo_config.branch_template())?
+            .into_iter()
+            .find(|(_, issue)| *issue == number)
+            .ok_or_else(|| anyhow::anyhow!("No task branch found for issue #{number}"))?,
+        None => {
+            let branch = current
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("Not on a branch, pass the issue number"))?;
+            let issue = state
+                .task(&branch)
+                .map(|task| task.issue)
+                .or_else(|| issue_number_from_branch(&branch, repo_config.branch_template()))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Current branch {branch} is not a valid issue branch")
+                })?;
+            (branch, issue)
+        }
+    };
+
+    let current_worktree = repo.workdir().ok().map(normalize_path);
+    let worktree = repo
+        .worktrees()?
+        .into_iter()
+        .find(|worktree| worktree.branch.as_deref() == Some(branch.as_str()));
+    let checked_out_here = worktree
+        .as_ref()
+        .is_some_and(|worktree| Some(normalize_path(&worktree.path)) == current_worktree);
+    if let Some(worktree) = &worktree {
+        if checked_out_here && worktree.name.is_some() {
+            return Err(anyhow::anyhow!(
+                "Task #{issue_number} lives in this worktree, run abandon from the main worktree"
+            ));
+        }
+        if !checked_out_here && worktree.name.is_none() {
+            return Err(anyhow::anyhow!(
+                "Branch {branch} is checked out in the main worktree {}, switch it to another branch first",
+                normalize_path(&worktree.path).display()
+            ));
+        }
+    }
+
+    let unpushed = repo.unpushed_commits(&branch, base)?;
+    let prompt = if unpushed > 0 {
+        format!(
+            "Abandon task #{issue_number}? {unpushed} commit(s) on {branch} were never pushed and will be lost"
+        )
+    } else {
+        format!("Abandon task #{issue_number} and delete branch {branch}?")
+    };
+    if !input_provider.confirm(&prompt, false)? {
+        return Err(Error::Cancelled("Aborted".to_string()).into());
+    }
+    if checked_out_here {
+        handle_dirty_tree(&mut repo, input_provider, base)?;
+        if repo.local_branch_exists(base) {
+            repo.checkout_branch(base)?;
+        } else {
+            repo.create_branch(base, &repo.base_ref(base))?;
+        }
+        println!("Switched to branch {base}");
+    } else if let Some(worktree) = &worktree {
+        repo.remove_worktree(worktree)?;
+        println!(
+            "Removed worktree {}",
+            normalize_path(&worktree.path).display()
+        );
+    }
+    repo.delete_branch(&branch)?;
+    println!("Deleted branch {branch}");
+    if delete_remote && repo.remote_branch_exists(&branch) {
+        repo.delete_remote_branch(&branch, &token)?;
+        println!("Deleted branch {branch} on origin");
+    }
+    state.remove(&branch);
+    state.save()?;
+
+    // Last, so that a failed local step run again doesn't post the comment twice
+    let client = GitHubClient::new(&token, settings.host(), owner, repo_name)?;
+    let mut failures = Vec::new();
+    if let Err(err) = client
+        .remove_label_from_issue(issue_number, WORKING_LABEL)
+        .await
+    {
+        failures.push(err);
+    }
+    match client.current_user_login().await {
+        Ok(login) => {
+            if let Err(err) = client.remove_assignee(issue_number, &login).await {
+                failures.push(err);
+            }
+        }
+        Err(err) => failures.push(err),
+    }
+    if let Some(comment) = &comment {
+        match client.comment_on_issue(issue_number, comment).await {
+            Ok(()) => println!("Comment posted on issue #{issue_number}"),
+            Err(err) => failures.push(err),
+        }
+    }
+    for failure in &failures {
+        println!("{}", format!("{failure:#}").yellow());
+    }
+    println!("Task #{issue_number} abandoned");
+    Ok(())
+}
+
+/// Rebases the current task branch onto the freshly fetched base, or merges the base into it
+async fn sync_command(
+    profile: Option<&str>,
+    strategy: Option<SyncStrategy>,
+    push: bool,
+) -> Result<()> {
+    let repo = GitRepo::open()?;
+    let settings = Settings::load(Some(&repo), profile)?;
+    let token = settings.github_token().await?;
+    let repo_config = &settings.repo;
+    let state = TaskState::load(repo.common_dir())?;
+    let branch = repo.get_current_branch_name()?;
+    if state.task(&branch).is_none()
+        && issue_number_from_branch(&branch, repo_config.branch_template()).is_none()
+    {
+        return Err(anyhow::anyhow!(
+            "Current branch {} is not a valid issue branch",
+            branch
+        ));
+    }
+    if repo.is_dirty()? {
+        return Err(anyhow::anyhow!(
+            "You have uncommitted changes, commit or stash them before syncing"
+        ));
+    }
+    println!("Fetching origin...");
+    if let Err(err) = repo.fetch(&token) {
+        println!(
+            "{}",
+            format!("{err:#}, using what is available locally").yellow()
+        );
+    }
+    let base_ref = repo.base_ref(repo_config.base_branch());
+    let strategy = strategy.unwrap_or(repo_config.sync_strategy);
+    let outcome = match strategy {
+        SyncStrategy::Rebase => repo.rebase_onto(&base_ref)?,
+        SyncStrategy::Merge => repo.merge_from(&base_ref)?,
+    };
+    match &outcome {
+        SyncOutcome::UpToDate => {
+            println!("{} is already up to date with {base_ref}", branch.blue())
+        }
+        SyncOutcome::FastForwarded => println!("Fast-forwarded {} to {base_ref}", branch.blue()),
+        SyncOutcome::Rebased(count) => {
+            println!(
+                "Rebased {count} commit(s) of {} onto {base_ref}",
+                branch.blue()
+            )
+        }
+        SyncOutcome::Merged => println!("Merged {base_ref} into {}", branch.blue()),
+        SyncOutcome::Conflicts(paths) => {
+            println!("{}", "Stopped on conflicts in:".yellow());
+            for path in paths {
+                println!("  {path}");
+            }
+            let (resume, abort) = match strategy {
+                SyncStrategy::Rebase => ("git rebase --continue", "git rebase --abort"),
+                SyncStrategy::Merge => ("git commit", "git merge --abort"),
+            };
+            println!(
+                "Resolve them, `git add` the files and run `{resume}`, or give up with `{abort}`"
+            );
+            return Err(Error::Conflict(format!("Sync of {branch} stopped on conflicts")).into());
+        }
+    }
+
+    if push {
+        let rewritten = matches!(outcome, SyncOutcome::Rebased(_));
+        if rewritten && repo.remote_branch_exists(&branch) {
+            repo.force_push_with_lease(&branch, &token)?;
+        } else {
+            repo.push_branch(&branch, &token)?;
+        }
+        println!("Pushed {}", branch.blue());
+    }
+    Ok(())
+}
+
+enum CleanupVerdict {
+    Remove(String),
+    Keep(String),
+    InProgress,
+}
+
+/// Deletes task branches, locally and on origin, whose PR was merged or whose issue was closed
+async fn cleanup_command(profile: Option<&str>, dry_run: bool) -> Result<()> {
+    let repo = GitRepo::open()?;
+    let settings = Settings::load(Some(&repo), profile)?;
+    let token = settings.github_token().await?;
+    let (owner, repo_name) = repo.get_repo_owner_and_name()?;
+    let repo_config = &settings.repo;
+    let base = repo_config.base_branch();
+    let mut state = TaskState::load(repo.common_dir())?;
+    println!("Fetching origin...");
+    if let Err(err) = repo.fetch(&token) {
+        println!(
+            "{}",
+            format!("{err:#}, using what is available locally").yellow()
+        );
+    }
+    let client = GitHubClient::new(&token, settings.host(), owner, repo_name)?;
+    let current = repo.get_current_branch_name().ok();
+    let worktrees = repo.worktrees()?;
+    let base_ref = repo.base_ref(base);
+
+    let mut removed = 0;
+    for (branch, issue) in task_branches(&repo, &state, repo_config.branch_template())? {
+        let worktree = worktrees
+            .iter()
+            .find(|worktree| worktree.branch.as_deref() == Some(branch.as_str()));
+        let verdict = if current.as_deref() == Some(branch.as_str()) {
+            Ok(CleanupVerdict::Keep("it's the current branch".to_string()))
+        } else if worktree.is_some_and(|worktree| worktree.name.is_none()) {
+            Ok(CleanupVerdict::Keep(
+                "it's checked out in the main worktree".to_string(),
+            ))
+        } else {
+            cleanup_verdict(&repo, &client, &branch, issue, &base_ref).await
+        };
+        let reason = match verdict {
+            Ok(CleanupVerdict::Remove(reason)) => reason,
+            Ok(CleanupVerdict::Keep(reason)) => {
+                println!("Keeping {branch}: {reason}");
+                continue;
+            }
+            Ok(CleanupVerdict::InProgress) => continue,
+            Err(err) => {
+                println!("{}", format!("Skipping {branch}: {err:#}").yellow());
+                continue;
+            }
+        };
+        if dry_run {
+            println!("Would remove {} ({reason})", branch.blue());
+            removed += 1;
+            continue;
+        }
+        if let Some(worktree) = worktree {
+            if let Err(err) = repo.remove_worktree(worktree) {
+                println!("{}", format!("Skipping {branch}: {err:#}").yellow());
+                continue;
+            }
+            println!(
+                "Removed worktree {}",
+                normalize_path(&worktree.path).display()
+            );
+        }
+        // The verdict only covers the local branch, origin may have commits pushed from elsewhere
+        let delete_remote = repo.remote_branch_exists(&branch)
+            && match repo.commits_only_on_origin(&branch) {
+                Ok(0) => true,
+                Ok(count) => {
+                    println!(
+                        "{}",
+                        format!("Keeping origin/{branch}: {count} commit(s) aren't on {branch}")
+                            .yellow()
+                    );
+                    false
+                }
+                Err(err) => {
+                    println!("{}", format!("Keeping origin/{branch}: {err:#}").yellow());
+                    false
+                }
+            };
+        if let Err(err) = repo.delete_branch(&branch) {
+            println!("{}", format!("Skipping {branch}: {err:#}").yellow());
+            continue;
+        }
+        if delete_remote && let Err(err) = repo.delete_remote_branch(&branch, &token) {
+            println!("{}", format!("{err:#}").yellow());
+        }
+        state.remove(&branch);
+        println!("Removed {} ({reason})", branch.blue());
+        removed += 1;
+    }
+    state.save()?;
+
+    match (removed, dry_run) {
+        (0, _) => println!("Nothing to clean up"),
+        (n, true) => println!("{n} branch(es) would be removed"),
+        (n, false) => println!("Removed {n} branch(es)"),
+    }
+    Ok(())
+}
+
+/// Whether a task branch is done with. A branch is only removed when that loses no work:
+/// everything on it is in the merged PR, or, for a closed issue, in the base branch.
+async fn cleanup_verdict(
+    repo: &GitRepo,
+    client: &GitHubClient,
+    branch: &str,
+    issue: u64,
+    base_ref: &str,
+) -> Result<CleanupVerdict> {
+    if let Some(pr) = client.find_merged_pull_request(branch).await? {
+        // Squash and rebase merges rewrite commits, so compare against what the PR contained
+        return Ok(match repo.commits_not_in(branch, &pr.head_sha) {
+            Ok(0) => CleanupVerdict::Remove(format!("PR #{} was merged", pr.number)),
+            Ok(count) => CleanupVerdict::Keep(format!(
+                "PR #{} was merged, but {count} commit(s) were added since",
+                pr.number
+            )),
+            Err(_) => CleanupVerdict::Keep(format!(
+                "PR #{} was merged, but its last commit isn't available locally",
+                pr.number
+            )),
+        });
+    }
+    if client.is_issue_closed(issue).await? {
+        let unmerged = repo.commits_not_in(branch, base_ref)?;
+        return Ok(if unmerged == 0 {
+            CleanupVerdict::Remove(format!("issue #{issue} is closed"))
+        } else {
+            CleanupVerdict::Keep(format!(
+                "issue #{issue} is closed, but {unmerged} commit(s) aren't merged into {base_ref}"
+            ))
+        });
+    }
+    Ok(CleanupVerdict::InProgress)
+}
+
+/// Titles of the open issues by number
+async fn issue_titles(settings: &Settings, repo: &GitRepo) -> Result<HashMap<u64, String>> {
+    let token = settings.github_token().await?;
+    let (owner, repo_name) = repo.get_repo_owner_and_name()?;
+    let client = GitHubClient::new(&token, settings.host(), owner, repo_name)?;
+    let issues = client.list_open_issues().await?;
+    Ok(issues
+        .into_iter()
+        .map(|issue| (issue.number, issue.title))
+        .collect())
+}
+
+async fn config_command(profile: Option<&str>, action: ConfigAction) -> Result<()> {
+    match action {
+        ConfigAction::Show { origin } => config_show_command(profile, origin).await,
+        ConfigAction::Get { key, origin } => config_get_command(profile, &key, origin).await,
+        ConfigAction::Set { key, value, scope } => {
+            let repo = GitRepo::open().ok();
+            if key == TOKEN_KEY {
+                if scope.scope() == Some(Scope::Repository) {
+                    return Err(anyhow::anyhow!(
+                        "{TOKEN_KEY} can't be set in {REPO_CONFIG_FILE}, it would be committed with the repository"
+                    ));
+                }
+                let location = Config::set_github_token(&value)?;
+                println!("Github token saved in the {location}");
+                return Ok(());
+            }
+            let scope = scope.scope().unwrap_or(Scope::Local);
+            let path = set_setting(scope, repo.as_ref(), &key, &value)?;
+            println!("Set {key} in {}", path.display());
+            Ok(())
+        }
+        ConfigAction::Unset { key, scope } => {
+            let repo = GitRepo::open().ok();
+            if key == TOKEN_KEY {
+                remove_secret(GITHUB_TOKEN_SECRET)?;
+                println!("Github token removed");
+                return Ok(());
+            }
+            let scope = scope.scope().unwrap_or(Scope::Local);
+            match unset_setting(scope, repo.as_ref(), &key)? {
+                Some(path) => println!("Removed {key} from {}", path.display()),
+                None => println!(
+                    "{key} is not set in {}",
+                    scope.path(repo.as_ref())?.display()
+                ),
+            }
+            Ok(())
+        }
+        ConfigAction::Edit { scope } => {
+            let repo = GitRepo::open().ok();
+            let scope = scope.scope().unwrap_or(Scope::Local);
+            let path = scope.path(repo.as_ref())?;
+            open_in_editor(&path)?;
+            if report_problems(scope, repo.as_ref())? {
+                return Err(anyhow::anyhow!(
+                    "{} has problems, run 'config edit' again to fix them",
+                    path.display()
+                ));
+            }
+            Ok(())
+        }
+        ConfigAction::Validate { scope } => {
+            let repo = GitRepo::open().ok();
+            let scopes = match scope.scope() {
+                Some(scope) => vec![scope],
+                // Outside a repository only the global file can be checked
+                None => Scope::ALL
+                    .into_iter()
+                    .filter(|scope| scope.path(repo.as_ref()).is_ok())
+                    .collect(),
+            };
+            let mut has_problems = false;
+            for scope in scopes {
+                has_problems |= report_problems(scope, repo.as_ref())?;
+            }
+            if has_problems {
+                return Err(anyhow::anyhow!("Some settings are invalid"));
+            }
+            Ok(())
+        }
+        ConfigAction::Schema { global } => {
+            println!("{}", serde_json::to_string_pretty(&json_schema(global))?);
+            Ok(())
+        }
+        ConfigAction::Profiles { action } => profiles_command(profile, action),
+    }
+}
+
+/// Every setting in effect, with the token found outside the configuration if there's one
+async fn effective_settings(settings: &Settings) -> Result<Vec<(String, Value, Origin)>> {
+    // A token that isn't configured may still come from the environment, gh or git
+    let external_token = settings
+        .token()
+        .await?
+        .filter(|token| token.source != TokenSource::Config);
+    Ok(settings
+        .entries()
+        .into_iter()
+        .map(|(key, value, origin)| match &external_token {
+            Some(token) if key == TOKEN_KEY => (
+                key,
+                Value::String(token.value.clone()),
+                Origin::External(token.source),
+            ),
+            _ => (key, value, origin),
+        })
+        .collect())
+}
+
+fn display_value(key: &str, value: &Value) -> String {
+    match value {
+        Value::Null => "(not set)".dimmed().to_string(),
+        // Tokens are never printed, not even in part
+        Value::String(_) if key == TOKEN_KEY => "********".to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn display_origin(origin: &Origin) -> String {
+    match origin {
+        Origin::Default => origin.to_string().dimmed().to_string(),
+        origin => origin.to_string(),
+    }
+}
+
+/// Prints every setting in effect, optionally with the layer it was read from
+async fn config_show_command(profile: Option<&str>, show_origin: bool) -> Result<()> {
+    let repo = GitRepo::open().ok();
+    let settings = Settings::load(repo.as_ref(), profile)?;
+    for (key, value, origin) in effective_settings(&settings).await? {
+        let value = display_value(&key, &value);
+        if show_origin {
+            println!("{key} = {value}  ({})", display_origin(&origin));
+        } else {
+            println!("{key} = {value}");
+        }
+    }
+    Ok(())
+}
+
+/// Prints a single setting, strings without quotes so the output can be used in scripts
+async fn config_get_command(profile: Option<&str>, key: &str, show_origin: bool) -> Result<()> {
+    check_key(key)?;
+    let repo = GitRepo::open().ok();
+    let settings = Settings::load(repo.as_ref(), profile)?;
+    let (_, value, origin) = effective_settings(&settings)
+        .await?
+        .into_iter()
+        .find(|(name, _, _)| name == key)
+        .ok_or_else(|| anyhow::anyhow!("Unknown setting '{key}'"))?;
+    let value = match value {
+        Value::Null => return Err(anyhow::anyhow!("{key} is not set")),
+        Value::String(text) if key != TOKEN_KEY => text,
+        value => display_value(key, &value),
+    };
+    if show_origin {
+        println!("{value}  ({})", display_origin(&origin));
+    } else {
+        println!("{value}");
+    }
+    Ok(())
+}
+
+/// Checks the config file of `scope` and prints what's wrong with it, returns whether anything was
+fn report_problems(scope: Scope, repo: Option<&GitRepo>) -> Result<bool> {
+    let Some((path, problems)) = validate_scope(scope, repo)? else {
+        return Ok(false);
+    };
+    if problems.is_empty() {
+        println!("{}: {}", path.display(), "ok".green());
+        return Ok(false);
+    }
+    println!("{}:", path.display());
+    for problem in &problems {
+        println!("{}", format!("  {problem}").yellow());
+    }
+    Ok(true)
+}
+
+/// Opens a file in the editor from `VISUAL` or `EDITOR`, waiting for it to be closed
+fn open_in_editor(path: &std::path::Path) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|editor| !editor.is_empty()))
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let status = shell(&format!("{editor} \"{}\"", path.display()))
+        .status()
+        .with_context(|| format!("Failed to start the editor '{editor}'"))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "The editor '{editor}' exited with {status}"
+        ));
+    }
+    Ok(())
+}
+
+/// Logs in with GitHub's device flow: the user enters a code in the browser while this waits for
+/// the token
+async fn login_command(
+    profile: Option<&str>,
+    client_id: Option<String>,
+    mut scopes: Vec<String>,
+) -> Result<()> {
+    let repo = GitRepo::open().ok();
+    let settings = Settings::load(repo.as_ref(), profile)?;
+    let client_id = client_id
+        .or_else(|| settings.repo.oauth_client_id.clone())
+        .ok_or_else(|| {
+            Error::ConfigMissing(
+                "No OAuth app to log in with. Register one with the device flow enabled, then pass its client id with --client-id or set GIT_TASK_OAUTH_CLIENT_ID"
+                    .to_string(),
+            )
+        })?;
+    if scopes.is_empty() {
+        scopes = DEFAULT_SCOPES.iter().map(ToString::to_string).collect();
+    }
+    let flow = DeviceFlow::new(settings.repo.oauth_url(), &client_id)?;
+    let code = flow.request_code(&scopes).await?;
+    println!(
+        "Open {} and enter the code {}",
+        code.verification_uri,
+        code.user_code.bold()
+    );
+    println!("Waiting for the code to be entered...");
+    let token = flow.wait_for_token(&code).await?;
+    match &settings.profile {
+        Some((name, _)) => {
+            let location = Config::set_profile_token(name, &token)?;
+            println!("Logged in, the token of profile {name} saved in the {location}");
+        }
+        None => {
+            let location = Config::set_github_token(&token)?;
+            println!("Logged in, Github token saved in the {location}");
+        }
+    }
+    Ok(())
+}
+
+/// Lists, adds or removes profiles
+fn profiles_command(selected: Option<&str>, action: ProfileAction) -> Result<()> {
+    match action {
+        ProfileAction::List => {
+            let config = Config::load()?;
+            if config.profiles.is_empty() {
+                println!("No profiles, add one with 'config profiles add <NAME>'");
+                return Ok(());
+            }
+            let origin = GitRepo::open().ok().and_then(|repo| repo.origin().ok());
+            let in_use = match &origin {
+                Some(origin) => config
+                    .select_profile(selected, &origin.host, Some(&origin.owner))?
+                    .map(|(name, _)| name),
+                None => None,
+            };
+            for (name, profile) in &config.profiles {
+                let marker = if in_use == Some(name.as_str()) {
+                    "*"
+                } else {
+                    " "
+                };
+                let owners = if profile.owners.is_empty() {
+                    "any owner".to_string()
+                } else {
+                    profile.owners.join(", ")
+                };
+                println!("{marker} {name}: {} ({owners})", profile.host());
+                if !profile.labels.is_empty() {
+                    println!("    labels: {}", profile.labels.join(", "));
+                }
+                let identity: Vec<&str> =
+                    [profile.user_name.as_deref(), profile.user_email.as_deref()]
+                        .into_iter()
+                        .flatten()
+                        .collect();
+                if !identity.is_empty() {
+                    println!("    author: {}", identity.join(" "));
+                }
+                match load_secret(&profile_token_secret(name))? {
+                    Some((_, location)) => println!("    token: stored in the {location}"),
+                    None => println!("    token: {}", "the default one".dimmed()),
+                }
+            }
+            Ok(())
+        }
+        ProfileAction::Add {
+            name,
+            host,
+            owners,
+            labels,
+            user_name,
+            user_email,
+            token,
+        } => {
+            let profile = Profile {
+                host,
+                owners,
+                labels,
+                user_name,
+                user_email,
+            };
+            Config::save_profile(&name, &profile)?;
+            println!("Profile {name} saved");
+            if let Some(token) = token {
+                let location = Config::set_profile_token(&name, &token)?;
+                println!("Its Github token saved in the {location}");
+            }
+            Ok(())
+        }
+        ProfileAction::Remove { name } => {
+            if Config::remove_profile(&name)? {
+                println!("Profile {name} removed");
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Profile '{name}' not found"))
+            }
+        }
+    }
+}
+
+/// Lists task branches with their issue, worktree and draft PR, marking the current one
+fn status_command(profile: Option<&str>) -> Result<()> {
+    let repo = GitRepo::open()?;
+    let settings = Settings::load(Some(&repo), profile)?;
+    let state = TaskState::load(repo.common_dir())?;
+    let current = repo.get_current_branch_name().ok();
+    let worktrees = repo.worktrees()?;
+    let current_worktree = repo.workdir().ok().map(normalize_path);
+
+    let tasks = task_branches(&repo, &state, settings.repo.branch_template())?;
+
+    match current
+        .as_deref()
+        .and_then(|branch| tasks.iter().find(|(task_branch, _)| task_branch == branch))
+    {
+        Some((branch, issue)) => println!("Working on #{issue} in {branch}"),
+        None => println!("Not on a task branch"),
+    }
+    if tasks.is_empty() {
+        return Ok(());
+    }
+    println!("\nTasks in progress:");
+    for (branch, issue) in &tasks {
+        let mut line = format!("#{} {}", issue.to_string().red(), branch.blue());
+        let worktree = worktrees
+            .iter()
+            .find(|worktree| worktree.branch.as_deref() == Some(branch.as_str()));
+        if let Some(worktree) = worktree {
+            let path = normalize_path(&worktree.path);
+            if current_worktree.as_ref() == Some(&path) {
+                line.push_str(&format!(" {}", "(current)".green()));
+            } else {
+                line.push_str(&format!(" checked out in {}", path.display()));
+            }
+        }
+        if let Some(pr) = state.task(branch).and_then(|task| task.draft_pr) {
+            line.push_str(&format!(" draft PR #{pr}"));
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
+
+async fn list_command(profile: Option<&str>) -> Result<()> {
     let repo = GitRepo::open()?;
+    let settings = Settings::load(Some(&repo), profile)?;
+    let token = settings.github_token().await?;
     let (owner, repo_name) = repo.get_repo_owner_and_name()?;
     println!("Fetching issues from {owner} - {repo_name} ");
-    let client = GitHubClient::new(&token, owner.clone(), repo_name.clone())?;
+    let client = GitHubClient::new(&token, settings.host(), owner.clone(), repo_name.clone())?;
     let issues = client.list_open_issues().await?;
     if issues.is_empty() {
         print!("No open issues found");
@@ -0,0 +1,203 @@
+use crate::error::Error;
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Scopes asked for when none are given: issues, pull requests and pushing to private repositories
+pub const DEFAULT_SCOPES: [&str; 1] = ["repo"];
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// What the user needs to authorize the device: the code to enter and where
+#[derive(Debug, Deserialize)]
+pub struct DeviceCode {
+    device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// Seconds the codes stay valid
+    expires_in: u64,
+    /// Seconds to wait between polls
+    interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+    interval: Option<u64>,
+}
+
+/// GitHub's OAuth device authorization flow, against `base_url` (`https://github.com`, or a
+/// GitHub Enterprise or stand-in server)
+pub struct DeviceFlow {
+    http: reqwest::Client,
+    base_url: String,
+    client_id: String,
+}
+
+impl DeviceFlow {
+    pub fn new(base_url: &str, client_id: &str) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("git-issue-flow/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .with_context(|| "Failed to create the HTTP client")?;
+        Ok(DeviceFlow {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+        })
+    }
+    /// Asks for a device and user code
+    pub async fn request_code(&self, scopes: &[String]) -> Result<DeviceCode> {
+        let url = format!("{}/login/device/code", self.base_url);
+        let response = self
+            .http
+            .post(&url)
+            .header("Accept", "application/json")
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("scope", &scopes.join(" ")),
+            ])
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {url}"))?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!("{url} answered {status}: {body}"));
+        }
+        // Errors such as a wrong client id come back as 200 too
+        if let Ok(TokenResponse {
+            error: Some(error),
+            error_description,
+            ..
+        }) = serde_json::from_str(&body)
+        {
+            return Err(anyhow!(
+                "Failed to start the login: {}",
+                error_description.unwrap_or(error)
+            ));
+        }
+        serde_json::from_str(&body).with_context(|| format!("Unexpected answer from {url}: {body}"))
+    }
+    /// Polls until the user has entered the code, returns the access token
+    pub async fn wait_for_token(&self, code: &DeviceCode) -> Result<String> {
+        let url = format!("{}/login/oauth/access_token", self.base_url);
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval);
+        loop {
+            tokio::time::sleep(interval).await;
+            if Instant::now() > deadline {
+                return Err(anyhow!(
+                    "The code expired before it was entered, log in again"
+                ));
+            }
+            let response: TokenResponse = self
+                .http
+                .post(&url)
+                .header("Accept", "application/json")
+                .form(&[
+                    ("client_id", self.client_id.as_str()),
+                    ("device_code", &code.device_code),
+                    ("grant_type", GRANT_TYPE),
+                ])
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach {url}"))?
+                .json()
+                .await
+                .with_context(|| format!("Unexpected answer from {url}"))?;
+            if let Some(token) = response.access_token {
+                return Ok(token);
+            }
+            match response.error.as_deref() {
+                Some("authorization_pending") => {}
+                // GitHub wants polls spaced further apart from now on
+                Some("slow_down") => {
+                    interval = response
+                        .interval
+                        .map_or(interval + Duration::from_secs(5), Duration::from_secs);
+                }
+                Some("expired_token") => {
+                    return Err(anyhow!(
+                        "The code expired before it was entered, log in again"
+                    ));
+                }
+                Some("access_denied") => {
+                    return Err(Error::Cancelled("The login was cancelled".to_string()).into());
+                }
+                Some(error) => {
+                    return Err(anyhow!(
+                        "Login failed: {}",
+                        response.error_description.as_deref().unwrap_or(error)
+                    ));
+                }
+                None => return Err(anyhow!("Unexpected answer from {url}")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::stand_in_server;
+
+    /// GitHub answers the OAuth endpoints with 200 even when it refuses, the body says why
+    const OK: &str = "HTTP/1.1 200 OK";
+
+    #[tokio::test]
+    async fn should_poll_until_the_code_is_entered() {
+        let (url, requests) = stand_in_server(vec![
+            (
+                OK,
+                r#"{"device_code":"dc","user_code":"ABCD-1234","verification_uri":"https://github.com/login/device","expires_in":900,"interval":0}"#,
+            ),
+            (OK, r#"{"error":"authorization_pending"}"#),
+            (OK, r#"{"error":"slow_down","interval":0}"#),
+            (
+                OK,
+                r#"{"access_token":"gho_token","token_type":"bearer","scope":"repo"}"#,
+            ),
+        ]);
+        let flow = DeviceFlow::new(&url, "client").unwrap();
+        let code = flow.request_code(&["repo".to_string()]).await.unwrap();
+        assert_eq!(code.user_code, "ABCD-1234");
+        assert_eq!(flow.wait_for_token(&code).await.unwrap(), "gho_token");
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[0],
+            "/login/device/code client_id=client&scope=repo"
+        );
+        assert_eq!(requests.len(), 4);
+        assert!(
+            requests[3].starts_with("/login/oauth/access_token client_id=client&device_code=dc")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_stop_when_the_login_is_refused() {
+        let (url, _) = stand_in_server(vec![(OK, r#"{"error":"access_denied"}"#)]);
+        let flow = DeviceFlow::new(&url, "client").unwrap();
+        let code = DeviceCode {
+            device_code: "dc".to_string(),
+            user_code: "ABCD-1234".to_string(),
+            verification_uri: String::new(),
+            expires_in: 900,
+            interval: 0,
+        };
+        let err = flow.wait_for_token(&code).await.unwrap_err();
+        assert_eq!(err.to_string(), "The login was cancelled");
+
+        let (url, _) = stand_in_server(vec![(
+            OK,
+            r#"{"error":"device_flow_disabled","error_description":"Device Flow must be explicitly enabled for this App"}"#,
+        )]);
+        let flow = DeviceFlow::new(&url, "client").unwrap();
+        let err = flow.request_code(&[]).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Device Flow must be explicitly enabled")
+        );
+    }
+}
@@ -0,0 +1,121 @@
+/// Matches a repository path against a gitignore-style pattern, as used by CODEOWNERS.
+///
+/// - a pattern without a slash (other than a trailing one) matches at any depth
+/// - a leading or inner slash anchors the pattern to the repository root
+/// - `*` and `?` match within a single path segment, `**` matches any number of segments
+/// - a pattern matching a directory also matches everything inside it,
+///   except for patterns ending in `/*`, which only cover the files directly in that directory
+/// - a trailing slash makes the pattern match directories only
+pub fn path_matches(pattern: &str, path: &str) -> bool {
+    let dir_only = pattern.ends_with('/');
+    let trimmed = pattern.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return false;
+    }
+    let anchored = trimmed.contains('/');
+    let trimmed = trimmed.trim_start_matches('/');
+    let mut segments: Vec<&str> = trimmed.split('/').collect();
+    if !anchored {
+        segments.insert(0, "**");
+    }
+    let path: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    if !dir_only && segments_match(&segments, &path) {
+        return true;
+    }
+    if anchored && trimmed.ends_with("/*") {
+        return false;
+    }
+    // Any directory containing the path matches, so does the path
+    (1..path.len()).any(|len| segments_match(&segments, &path[..len]))
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(&"**") => (0..=path.len()).any(|skip| segments_match(&pattern[1..], &path[skip..])),
+        Some(segment) => {
+            !path.is_empty()
+                && wildcard_match(segment, path[0])
+                && segments_match(&pattern[1..], &path[1..])
+        }
+    }
+}
+
+/// Matches a single segment, `*` matches any run of characters and `?` a single one
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last star swallow one more character and retry
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_match_unanchored_patterns_at_any_depth() {
+        assert!(path_matches("*.js", "app.js"));
+        assert!(path_matches("*.js", "src/web/app.js"));
+        assert!(!path_matches("*.js", "src/app.ts"));
+        assert!(path_matches("*", "anything/at/all.txt"));
+    }
+
+    #[test]
+    fn should_anchor_patterns_with_slashes() {
+        assert!(path_matches("/build/logs/", "build/logs/out.txt"));
+        assert!(!path_matches("/build/logs/", "src/build/logs/out.txt"));
+        assert!(path_matches("docs/guide.md", "docs/guide.md"));
+        assert!(!path_matches("docs/guide.md", "api/docs/guide.md"));
+    }
+
+    #[test]
+    fn should_match_directory_contents() {
+        assert!(path_matches("apps/", "apps/web/index.html"));
+        assert!(path_matches("apps/", "src/apps/web/index.html"));
+        assert!(!path_matches("apps/", "apps"));
+        assert!(path_matches("/apps/github", "apps/github/main.rs"));
+    }
+
+    #[test]
+    fn should_only_match_direct_children_for_single_star() {
+        assert!(path_matches("/docs/*", "docs/getting-started.md"));
+        assert!(!path_matches(
+            "/docs/*",
+            "docs/build-app/troubleshooting.md"
+        ));
+    }
+
+    #[test]
+    fn should_match_double_star_across_segments() {
+        assert!(path_matches("src/api/**", "src/api/users/handler.rs"));
+        assert!(path_matches("**/logs", "deep/nested/logs/today.log"));
+        assert!(path_matches("docs/**/*.md", "docs/a/b/c.md"));
+        assert!(!path_matches("src/api/**", "src/web/api/handler.rs"));
+    }
+
+    #[test]
+    fn should_match_single_character_wildcards() {
+        assert!(path_matches("file?.txt", "file1.txt"));
+        assert!(!path_matches("file?.txt", "file12.txt"));
+        assert!(path_matches("*_test*.rs", "src/git_tests.rs"));
+    }
+}
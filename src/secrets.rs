@@ -0,0 +1,247 @@
+use anyhow::{Context, Result, anyhow};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use keyring::Entry;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Service name secrets are filed under in the system keyring
+const SERVICE: &str = "git-issue-flow";
+/// Name of the GitHub token among the secrets
+pub const GITHUB_TOKEN_SECRET: &str = "github_token";
+const NONCE_LEN: usize = 12;
+
+/// Where a secret is kept
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecretLocation {
+    Keyring,
+    EncryptedFile(PathBuf),
+}
+
+impl fmt::Display for SecretLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretLocation::Keyring => write!(f, "system keyring"),
+            SecretLocation::EncryptedFile(path) => write!(f, "encrypted file {}", path.display()),
+        }
+    }
+}
+
+/// Saves a secret in the system keyring, or in the encrypted secrets file when no keyring is
+/// available (e.g. a headless Linux box without the Secret Service)
+pub fn store_secret(name: &str, value: &str) -> Result<SecretLocation> {
+    if keyring_call(name, |entry| entry.set_password(value)).is_ok() {
+        // Don't leave an older copy behind that could shadow a later keyring failure
+        remove_file_secret(name)?;
+        return Ok(SecretLocation::Keyring);
+    }
+    let path = secrets_path()?;
+    let key = file_key(true)?.ok_or_else(|| anyhow!("Failed to create the secrets key"))?;
+    let mut secrets = read_secrets_file(&path)?;
+    secrets.insert(name.to_string(), encrypt(&key, name, value)?);
+    write_secrets_file(&path, &secrets)?;
+    Ok(SecretLocation::EncryptedFile(path))
+}
+
+/// Reads a secret from the system keyring, then from the encrypted secrets file
+pub fn load_secret(name: &str) -> Result<Option<(String, SecretLocation)>> {
+    if let Ok(value) = keyring_call(name, |entry| entry.get_password()) {
+        return Ok(Some((value, SecretLocation::Keyring)));
+    }
+    let path = secrets_path()?;
+    let secrets = read_secrets_file(&path)?;
+    let Some(encrypted) = secrets.get(name) else {
+        return Ok(None);
+    };
+    let key = file_key(false)?.ok_or_else(|| {
+        anyhow!(
+            "{} holds {name} but its key is gone, save the secret again",
+            path.display()
+        )
+    })?;
+    let value = decrypt(&key, name, encrypted)
+        .with_context(|| format!("Failed to decrypt {name} from {}", path.display()))?;
+    Ok(Some((value, SecretLocation::EncryptedFile(path))))
+}
+
+/// Deletes a secret from both the system keyring and the encrypted secrets file
+pub fn remove_secret(name: &str) -> Result<()> {
+    // Missing entries and a missing keyring are both fine here
+    let _ = keyring_call(name, |entry| entry.delete_credential());
+    remove_file_secret(name)
+}
+
+/// Runs a keyring operation on its own thread: the Secret Service backend blocks on an executor,
+/// which must not happen on a tokio worker thread
+fn keyring_call<T: Send>(
+    name: &str,
+    call: impl FnOnce(&Entry) -> keyring::Result<T> + Send,
+) -> keyring::Result<T> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| call(&Entry::new(SERVICE, name)?))
+            .join()
+            .unwrap_or_else(|_| {
+                Err(keyring::Error::PlatformFailure(
+                    "keyring call panicked".into(),
+                ))
+            })
+    })
+}
+
+fn secrets_path() -> Result<PathBuf> {
+    let dir = dirs::config_dir().with_context(|| "Failed to determine config directory")?;
+    Ok(dir.join("git-issue-flow").join("secrets.json"))
+}
+
+/// Key of the encrypted secrets file. It lives in the local data directory rather than next to
+/// the secrets, so copying or syncing the config directory doesn't carry the key along.
+fn file_key(create: bool) -> Result<Option<Key>> {
+    let dir = dirs::data_local_dir().with_context(|| "Failed to determine data directory")?;
+    let path = dir.join("git-issue-flow").join("secrets.key");
+    if path.exists() {
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        if bytes.len() != 32 {
+            return Err(anyhow!("Secrets key {path:?} is damaged"));
+        }
+        return Ok(Some(*Key::from_slice(&bytes)));
+    }
+    if !create {
+        return Ok(None);
+    }
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    write_private(&path, key.as_slice())?;
+    Ok(Some(key))
+}
+
+fn read_secrets_file(path: &Path) -> Result<BTreeMap<String, String>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {path:?}"))
+}
+
+fn write_secrets_file(path: &Path, secrets: &BTreeMap<String, String>) -> Result<()> {
+    let content = serde_json::to_string_pretty(secrets)?;
+    write_private(path, content.as_bytes())
+}
+
+fn remove_file_secret(name: &str) -> Result<()> {
+    let path = secrets_path()?;
+    let mut secrets = read_secrets_file(&path)?;
+    if secrets.remove(name).is_some() {
+        write_secrets_file(&path, &secrets)?;
+    }
+    Ok(())
+}
+
+/// Writes a file only the current user can read
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create a directory: {parent:?}"))?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("Failed to restrict permissions of {path:?}"))?;
+        }
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .with_context(|| format!("Failed to write {path:?}"))
+}
+
+/// Encrypts a secret as hex of the nonce followed by the ciphertext. The name is authenticated
+/// along with it, so values can't be swapped between names in the file.
+fn encrypt(key: &Key, name: &str, value: &str) -> Result<String> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: value.as_bytes(),
+        aad: name.as_bytes(),
+    };
+    let ciphertext = ChaCha20Poly1305::new(key)
+        .encrypt(&nonce, payload)
+        .map_err(|_| anyhow!("Failed to encrypt {name}"))?;
+    Ok(to_hex(nonce.iter().chain(&ciphertext)))
+}
+
+fn decrypt(key: &Key, name: &str, encrypted: &str) -> Result<String> {
+    let bytes = from_hex(encrypted).ok_or_else(|| anyhow!("{name} is not valid hex"))?;
+    if bytes.len() < NONCE_LEN {
+        return Err(anyhow!("{name} is too short"));
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: name.as_bytes(),
+    };
+    let plaintext = ChaCha20Poly1305::new(key)
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow!("{name} doesn't match the secrets key"))?;
+    String::from_utf8(plaintext).with_context(|| format!("{name} is not valid UTF-8"))
+}
+
+fn to_hex<'a>(bytes: impl Iterator<Item = &'a u8>) -> String {
+    bytes.map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_decrypt_what_was_encrypted() {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let encrypted = encrypt(&key, "github_token", "ghp_secret").unwrap();
+        assert!(!encrypted.contains("ghp_secret"));
+        assert_eq!(
+            decrypt(&key, "github_token", &encrypted).unwrap(),
+            "ghp_secret"
+        );
+        // Same value, fresh nonce
+        assert_ne!(
+            encrypt(&key, "github_token", "ghp_secret").unwrap(),
+            encrypted
+        );
+    }
+
+    #[test]
+    fn should_refuse_other_keys_and_names() {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let encrypted = encrypt(&key, "github_token", "ghp_secret").unwrap();
+        let other_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        assert!(decrypt(&other_key, "github_token", &encrypted).is_err());
+        assert!(decrypt(&key, "app_key", &encrypted).is_err());
+        assert!(decrypt(&key, "github_token", "zz").is_err());
+    }
+
+    #[test]
+    fn should_round_trip_hex() {
+        assert_eq!(to_hex([0u8, 15, 255].iter()), "000fff");
+        assert_eq!(from_hex("000fff"), Some(vec![0, 15, 255]));
+        assert_eq!(from_hex("0g"), None);
+        assert_eq!(from_hex("abc"), None);
+    }
+}
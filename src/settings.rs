@@ -0,0 +1,948 @@
+use crate::auth::{Token, TokenSource, find_token};
+use crate::config::{
+    Config, DEFAULT_HOST, Profile, RepoConfig, edit_config_file, get_config_path,
+    profile_token_secret,
+};
+use crate::error::Error;
+use crate::git::{GitRepo, RemoteUrl};
+use crate::github_app::AppInstallation;
+use crate::secrets::{GITHUB_TOKEN_SECRET, SecretLocation, load_secret};
+use anyhow::{Context, Result, anyhow};
+use schemars::{JsonSchema, schema_for};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Repository settings committed with the code, shared by everyone working on it
+pub const REPO_CONFIG_FILE: &str = ".git-task.toml";
+/// Personal overrides for a single clone, kept in the git directory so they're never committed
+pub const LOCAL_CONFIG_FILE: &str = "git-task.toml";
+/// Prefix of the environment variables overriding settings, e.g. `GIT_TASK_BASE_BRANCH`
+pub const ENV_PREFIX: &str = "GIT_TASK_";
+pub const TOKEN_KEY: &str = "github_token";
+
+/// Where a setting was read from, lowest precedence first
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    Default,
+    Profile(String),
+    /// The top-level settings of the global config file
+    Global(PathBuf),
+    /// The `repos` entry of a repository in the global config file
+    GlobalRepo(PathBuf, String),
+    Repository(PathBuf),
+    Local(PathBuf),
+    Env(String),
+    Secret(SecretLocation),
+    /// A token found outside the configuration
+    External(TokenSource),
+}
+
+impl Origin {
+    /// The config file the setting was read from
+    fn file(&self) -> Option<&Path> {
+        match self {
+            Origin::Global(path)
+            | Origin::GlobalRepo(path, _)
+            | Origin::Repository(path)
+            | Origin::Local(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::Profile(name) => write!(f, "profile {name}"),
+            Origin::Global(path) => write!(f, "global config {}", path.display()),
+            Origin::GlobalRepo(path, repo) => {
+                write!(f, "repos.{repo} in global config {}", path.display())
+            }
+            Origin::Repository(path) => write!(f, "repository config {}", path.display()),
+            Origin::Local(path) => write!(f, "local config {}", path.display()),
+            Origin::Env(var) => write!(f, "environment variable {var}"),
+            Origin::Secret(location) => write!(f, "{location}"),
+            Origin::External(source) => write!(f, "{source}"),
+        }
+    }
+}
+
+/// A file settings can be written to, see [`Settings::load`] for how they're layered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// This repository's entry in the global config file
+    Global,
+    /// The top-level settings of the global config file, for every repository
+    GlobalDefault,
+    /// `.git-task.toml`, shared with everyone working on the repository
+    Repository,
+    /// `git-task.toml` in the git directory, for this clone only
+    Local,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Global, Scope::Repository, Scope::Local];
+
+    pub fn path(self, repo: Option<&GitRepo>) -> Result<PathBuf> {
+        match (self, repo) {
+            (Scope::Global | Scope::GlobalDefault, _) => get_config_path(),
+            (Scope::Repository, Some(repo)) => Ok(repo.workdir()?.join(REPO_CONFIG_FILE)),
+            (Scope::Local, Some(repo)) => Ok(repo.common_dir().join(LOCAL_CONFIG_FILE)),
+            (_, None) => Err(anyhow!(
+                "Not in a git repository, there is no repository config"
+            )),
+        }
+    }
+}
+
+/// Settings in effect for a repository, merged from every configuration layer
+#[derive(Debug)]
+pub struct Settings {
+    /// Token from the configuration layers, other sources are only tried by [`Settings::token`]
+    pub github_token: Option<String>,
+    pub repo: RepoConfig,
+    /// Name and definition of the profile in use
+    pub profile: Option<(String, Profile)>,
+    /// Host of the GitHub server, tokens are looked up for it, see [`Settings::host`]
+    host: String,
+    /// Owner and name of the origin repository, a GitHub App token is limited to it
+    repository: Option<(String, String)>,
+    values: Map<String, Value>,
+    origins: HashMap<String, Origin>,
+}
+
+impl Settings {
+    /// Merges the layers, from lowest to highest precedence: the profile (`profile`, or the one
+    /// matching the origin remote), the global config file (its top-level settings, then its entry
+    /// for this repository), `.git-task.toml` in the working directory, `git-task.toml` in the git
+    /// directory and `GIT_TASK_*` environment variables. A value from a higher layer replaces the
+    /// lower one as a whole, lists are not combined. A profile's own token wins over the one saved
+    /// globally.
+    pub fn load(repo: Option<&GitRepo>, profile: Option<&str>) -> Result<Self> {
+        let origin = repo.and_then(|repo| repo.origin().ok());
+        let host = origin
+            .as_ref()
+            .map_or_else(|| DEFAULT_HOST.to_string(), |origin| origin.host.clone());
+        let mut settings = Settings {
+            github_token: None,
+            repo: RepoConfig::default(),
+            profile: None,
+            host,
+            repository: origin
+                .as_ref()
+                .map(|origin| (origin.owner.clone(), origin.repo.clone())),
+            values: Map::new(),
+            origins: HashMap::new(),
+        };
+        // Loading the global config moves a plaintext token out of it first
+        let config = Config::load()?;
+        let owner = origin.as_ref().map(|origin| origin.owner.as_str());
+        settings.profile = config
+            .select_profile(profile, &settings.host, owner)?
+            .map(|(name, profile)| (name.to_string(), profile.clone()));
+        settings.host = api_host(
+            origin.as_ref(),
+            settings
+                .profile
+                .as_ref()
+                .map(|(name, profile)| (name.as_str(), profile)),
+        )?;
+        if let Some((name, profile)) = &settings.profile
+            && !profile.labels.is_empty()
+        {
+            let mut layer = Map::new();
+            layer.insert("labels".to_string(), serde_json::to_value(&profile.labels)?);
+            settings.apply(Origin::Profile(name.clone()), layer)?;
+        }
+        let global_path = get_config_path()?;
+        let global = read_global_config(&global_path)?;
+        let repo_key = repo
+            .and_then(|repo| repo.get_repo_owner_and_name().ok())
+            .map(|(owner, name)| format!("{owner}/{name}"));
+        let (defaults, entry) = global_layers(&global, repo_key.as_deref());
+        settings.apply(Origin::Global(global_path.clone()), defaults)?;
+        if let Some(repo_key) = repo_key {
+            settings.apply(Origin::GlobalRepo(global_path.clone(), repo_key), entry)?;
+        }
+        if let Some((token, location)) = load_secret(GITHUB_TOKEN_SECRET)? {
+            let mut layer = Map::new();
+            layer.insert(TOKEN_KEY.to_string(), Value::String(token));
+            settings.apply(Origin::Secret(location), layer)?;
+        }
+        if let Some((name, _)) = &settings.profile
+            && let Some((token, _)) = load_secret(&profile_token_secret(name))?
+        {
+            let mut layer = Map::new();
+            layer.insert(TOKEN_KEY.to_string(), Value::String(token));
+            settings.apply(Origin::Profile(name.clone()), layer)?;
+        }
+        if let Some(repo) = repo {
+            if let Ok(workdir) = repo.workdir() {
+                let path = workdir.join(REPO_CONFIG_FILE);
+                let layer = read_toml_layer(&path)?;
+                if layer.contains_key(TOKEN_KEY) {
+                    return Err(anyhow!(
+                        "{TOKEN_KEY} can't be set in {}, it would be committed with the repository",
+                        path.display()
+                    ));
+                }
+                settings.apply(Origin::Repository(path), layer)?;
+            }
+            let path = repo.common_dir().join(LOCAL_CONFIG_FILE);
+            settings.apply(Origin::Local(path.clone()), read_toml_layer(&path)?)?;
+        }
+        for key in setting_keys() {
+            let var = format!("{ENV_PREFIX}{}", key.to_uppercase());
+            if let Ok(raw) = std::env::var(&var) {
+                let mut layer = Map::new();
+                layer.insert(key.clone(), value_from_str(&key, &raw));
+                settings.apply(Origin::Env(var), layer)?;
+            }
+        }
+
+        settings.github_token = settings
+            .values
+            .get(TOKEN_KEY)
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        settings.repo = serde_json::from_value(Value::Object(repo_values(&settings.values)))
+            .with_context(|| "Failed to combine the configured settings")?;
+        Ok(settings)
+    }
+    /// The GitHub App set up for the repository. Outside a repository there's nothing to limit
+    /// its token to, so it isn't used.
+    pub fn app(&self) -> Result<Option<AppInstallation>> {
+        let (Some(app_id), Some((owner, repo))) = (self.repo.app_id, &self.repository) else {
+            return Ok(None);
+        };
+        let private_key = self
+            .repo
+            .app_private_key
+            .as_deref()
+            .ok_or_else(|| anyhow!("app_id is set but app_private_key isn't"))?;
+        // Relative to the config file that set it, wherever the command is run from
+        let private_key = match self
+            .origins
+            .get("app_private_key")
+            .and_then(Origin::file)
+            .and_then(Path::parent)
+        {
+            Some(dir) => dir.join(private_key),
+            None => PathBuf::from(private_key),
+        };
+        Ok(Some(AppInstallation {
+            app_id,
+            private_key,
+            installation_id: self.repo.app_installation_id,
+            host: self.host.clone(),
+            owner: owner.clone(),
+            repo: repo.clone(),
+        }))
+    }
+    /// Host of the origin remote, or of the profile in use outside a repository. Tokens are looked
+    /// up for it and the API of this host is called.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+    /// The first token found in the configured token sources
+    pub async fn token(&self) -> Result<Option<Token>> {
+        find_token(
+            self.repo.token_sources(),
+            self.github_token.as_deref(),
+            &self.host,
+            self.app()?.as_ref(),
+        )
+        .await
+    }
+    pub async fn github_token(&self) -> Result<String> {
+        let token = self.token().await?.ok_or_else(|| {
+            let sources: Vec<String> = self
+                .repo
+                .token_sources()
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            Error::ConfigMissing(format!(
+                "GitHub token not found, looked in: {}. Save one with 'config --token <TOKEN>' or 'login'",
+                sources.join(", ")
+            ))
+        })?;
+        Ok(token.value)
+    }
+    /// Every known setting with the value in effect and where it came from
+    pub fn entries(&self) -> Vec<(String, Value, Origin)> {
+        let defaults = default_values();
+        setting_keys()
+            .into_iter()
+            .map(|key| {
+                let value = self
+                    .values
+                    .get(&key)
+                    .or_else(|| defaults.get(&key))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                let origin = self.origins.get(&key).cloned().unwrap_or(Origin::Default);
+                (key, value, origin)
+            })
+            .collect()
+    }
+    fn apply(&mut self, origin: Origin, layer: Map<String, Value>) -> Result<()> {
+        let keys = setting_keys();
+        if let Some(unknown) = layer.keys().find(|key| !keys.contains(key)) {
+            return Err(anyhow!(
+                "Unknown setting '{unknown}' in {origin}{}",
+                did_you_mean(unknown)
+            ));
+        }
+        for (key, value) in repo_values(&layer) {
+            check_value(&key, &value).with_context(|| format!("Invalid settings in {origin}"))?;
+        }
+        if let Some(token) = layer.get(TOKEN_KEY).filter(|token| !token.is_string()) {
+            return Err(anyhow!(
+                "{TOKEN_KEY} in {origin} should be a string, not {token}"
+            ));
+        }
+        for (key, value) in layer {
+            if value.is_null() {
+                continue;
+            }
+            self.origins.insert(key.clone(), origin.clone());
+            self.values.insert(key, value);
+        }
+        Ok(())
+    }
+}
+
+/// Host whose API is used: the origin remote's, or outside a repository the profile's. A profile
+/// picked by name for another host is refused, its token would go to the wrong server.
+fn api_host(origin: Option<&RemoteUrl>, profile: Option<(&str, &Profile)>) -> Result<String> {
+    match (origin, profile) {
+        (Some(origin), Some((name, profile)))
+            if !profile.host().eq_ignore_ascii_case(&origin.host) =>
+        {
+            Err(anyhow!(
+                "Profile '{name}' is for {}, but origin is on {}",
+                profile.host(),
+                origin.host
+            ))
+        }
+        (Some(origin), _) => Ok(origin.host.clone()),
+        (None, Some((_, profile))) => Ok(profile.host().to_string()),
+        (None, None) => Ok(DEFAULT_HOST.to_string()),
+    }
+}
+
+/// Names of all settings: the token and the fields of [`RepoConfig`]
+pub fn setting_keys() -> Vec<String> {
+    std::iter::once(TOKEN_KEY.to_string())
+        .chain(default_values().into_iter().map(|(key, _)| key))
+        .collect()
+}
+
+fn default_values() -> Map<String, Value> {
+    match serde_json::to_value(RepoConfig::default()) {
+        Ok(Value::Object(values)) => values,
+        _ => Map::new(),
+    }
+}
+
+fn repo_values(values: &Map<String, Value>) -> Map<String, Value> {
+    values
+        .iter()
+        .filter(|(key, _)| *key != TOKEN_KEY)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Keys of the global config file that aren't settings
+const GLOBAL_SECTIONS: [&str; 2] = ["repos", "profiles"];
+
+fn read_global_config(path: &Path) -> Result<Map<String, Value>> {
+    if !path.exists() {
+        return Ok(Map::new());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {path:?}"))?;
+    match serde_json::from_str(&content).with_context(|| "Failed to parse config file as JSON")? {
+        Value::Object(global) => Ok(global),
+        _ => Err(anyhow!("Config file {path:?} should contain a JSON object")),
+    }
+}
+
+/// The two layers of the global JSON file: its top-level settings, for every repository, and the
+/// `repos` entry of `repo_key`. A plaintext token at the top level is left to [`Config::load`],
+/// which moves it to the secret store.
+fn global_layers(
+    global: &Map<String, Value>,
+    repo_key: Option<&str>,
+) -> (Map<String, Value>, Map<String, Value>) {
+    let defaults = global
+        .iter()
+        .filter(|(key, _)| !GLOBAL_SECTIONS.contains(&key.as_str()) && *key != TOKEN_KEY)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let entry = repo_key
+        .and_then(|key| global.get("repos")?.get(key)?.as_object().cloned())
+        .unwrap_or_default();
+    (defaults, entry)
+}
+
+fn read_toml_layer(path: &Path) -> Result<Map<String, Value>> {
+    if !path.exists() {
+        return Ok(Map::new());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {path:?}"))?;
+    let table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse {} as TOML", path.display()))?;
+    match serde_json::to_value(table)? {
+        Value::Object(layer) => Ok(layer),
+        _ => Ok(Map::new()),
+    }
+}
+
+/// Reads a value given as a plain string, in an environment variable or on the command line:
+/// lists may be comma separated, other values are read as JSON when that makes a valid setting
+/// (`true`, `3`, `["a", "b"]`) and as a string otherwise
+fn value_from_str(key: &str, raw: &str) -> Value {
+    if key == TOKEN_KEY {
+        return Value::String(raw.to_string());
+    }
+    let is_list = default_values().get(key).is_some_and(Value::is_array);
+    if is_list && !raw.trim_start().starts_with('[') {
+        return raw
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| Value::String(item.to_string()))
+            .collect();
+    }
+    match serde_json::from_str::<Value>(raw) {
+        Ok(value) if is_valid_setting(key, &value) => value,
+        _ => Value::String(raw.to_string()),
+    }
+}
+
+fn is_valid_setting(key: &str, value: &Value) -> bool {
+    check_value(key, value).is_ok()
+}
+
+fn check_value(key: &str, value: &Value) -> Result<()> {
+    let mut layer = Map::new();
+    layer.insert(key.to_string(), value.clone());
+    serde_json::from_value::<RepoConfig>(Value::Object(layer))
+        .map_err(|err| anyhow!("Invalid value for {key}: {err}"))?;
+    // Task branches are told apart and matched to their issue by the number in their name
+    if key == "branch_template"
+        && let Some(template) = value.as_str()
+        && !template.contains("{number}")
+    {
+        return Err(anyhow!(
+            "Invalid value for {key}: '{template}' has no {{number}}, the issue couldn't be found from the branch name"
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_key(key: &str) -> Result<()> {
+    if setting_keys().iter().any(|known| known == key) {
+        Ok(())
+    } else {
+        Err(anyhow!("Unknown setting '{key}'{}", did_you_mean(key)))
+    }
+}
+
+/// Suggests the known setting closest to a misspelled one
+fn did_you_mean(key: &str) -> String {
+    setting_keys()
+        .into_iter()
+        .map(|known| (edit_distance(key, &known), known))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, known)| format!(", did you mean '{known}'?"))
+        .unwrap_or_default()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Checks a value given on the command line and writes it to the file of `scope`, returns the
+/// path of the file. The token isn't handled here, it's kept with the other secrets.
+pub fn set_setting(scope: Scope, repo: Option<&GitRepo>, key: &str, raw: &str) -> Result<PathBuf> {
+    check_key(key)?;
+    let value = value_from_str(key, raw);
+    check_value(key, &value)?;
+    let path = scope.path(repo)?;
+    match scope {
+        Scope::Global => {
+            let repo_key = repo_key(repo)?;
+            edit_config_file(|config| {
+                if let Some(entry) =
+                    object_entry(config, "repos").and_then(|repos| object_entry(repos, &repo_key))
+                {
+                    entry.insert(key.to_string(), value);
+                }
+            })?;
+        }
+        Scope::GlobalDefault => edit_config_file(|config| {
+            config.insert(key.to_string(), value);
+        })?,
+        Scope::Repository | Scope::Local => {
+            let value = toml_value(&value)?;
+            edit_toml_file(&path, |document| {
+                document[key] = toml_edit::Item::Value(value);
+            })?;
+        }
+    }
+    Ok(path)
+}
+
+/// Removes a setting from the file of `scope`, returns the path of the file when it was there
+pub fn unset_setting(scope: Scope, repo: Option<&GitRepo>, key: &str) -> Result<Option<PathBuf>> {
+    check_key(key)?;
+    let path = scope.path(repo)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut removed = false;
+    match scope {
+        Scope::Global => {
+            let repo_key = repo_key(repo)?;
+            edit_config_file(|config| {
+                if let Some(Value::Object(repos)) = config.get_mut("repos") {
+                    if let Some(Value::Object(entry)) = repos.get_mut(&repo_key) {
+                        removed = entry.remove(key).is_some();
+                        if entry.is_empty() {
+                            repos.remove(&repo_key);
+                        }
+                    }
+                    if repos.is_empty() {
+                        config.remove("repos");
+                    }
+                }
+            })?;
+        }
+        Scope::GlobalDefault => edit_config_file(|config| {
+            removed = config.remove(key).is_some();
+        })?,
+        Scope::Repository | Scope::Local => edit_toml_file(&path, |document| {
+            removed = document.remove(key).is_some();
+        })?,
+    }
+    Ok(removed.then_some(path))
+}
+
+/// Problems with the settings in the file of `scope`, `None` when there is no such file. The
+/// global file is checked as a whole, with the entries of every repository and profile.
+pub fn validate_scope(
+    scope: Scope,
+    repo: Option<&GitRepo>,
+) -> Result<Option<(PathBuf, Vec<String>)>> {
+    let path = scope.path(repo)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file: {path:?}"))?;
+    let problems = match scope {
+        Scope::Global | Scope::GlobalDefault => match serde_json::from_str(&content) {
+            Ok(Value::Object(config)) => global_problems(&config),
+            Ok(_) => vec!["the file should contain a JSON object".to_string()],
+            Err(err) => vec![format!("not valid JSON: {err}")],
+        },
+        Scope::Repository | Scope::Local => match toml::from_str::<toml::Table>(&content) {
+            Ok(table) => match serde_json::to_value(table)? {
+                Value::Object(layer) => layer_problems(&layer, scope == Scope::Local),
+                _ => Vec::new(),
+            },
+            Err(err) => vec![format!("not valid TOML: {}", err.message())],
+        },
+    };
+    Ok(Some((path, problems)))
+}
+
+fn global_problems(config: &Map<String, Value>) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (key, value) in config {
+        match (key.as_str(), value) {
+            (TOKEN_KEY, _) => problems.push(format!(
+                "{TOKEN_KEY} is saved in plaintext, run 'config' to move it to the secret store"
+            )),
+            ("repos", Value::Object(repos)) => {
+                for (name, entry) in repos {
+                    match entry {
+                        Value::Object(layer) => problems.extend(
+                            layer_problems(layer, true)
+                                .into_iter()
+                                .map(|problem| format!("repos.{name}: {problem}")),
+                        ),
+                        _ => problems.push(format!("repos.{name} should be an object")),
+                    }
+                }
+            }
+            ("profiles", Value::Object(profiles)) => {
+                let keys = schema_keys::<Profile>();
+                for (name, profile) in profiles {
+                    if let Value::Object(fields) = profile {
+                        problems.extend(
+                            fields
+                                .keys()
+                                .filter(|field| !keys.contains(field))
+                                .map(|field| format!("profiles.{name}: unknown key '{field}'")),
+                        );
+                    }
+                    if let Err(err) = serde_json::from_value::<Profile>(profile.clone()) {
+                        problems.push(format!("profiles.{name}: {err}"));
+                    }
+                }
+            }
+            ("repos" | "profiles", _) => problems.push(format!("{key} should be an object")),
+            _ if setting_keys().contains(key) => {
+                if let Err(err) = check_value(key, value) {
+                    problems.push(err.to_string());
+                }
+            }
+            _ => problems.push(format!("unknown key '{key}'{}", did_you_mean(key))),
+        }
+    }
+    problems
+}
+
+fn layer_problems(layer: &Map<String, Value>, allow_token: bool) -> Vec<String> {
+    let keys = setting_keys();
+    let mut problems = Vec::new();
+    for (key, value) in layer {
+        if key == TOKEN_KEY {
+            if !allow_token {
+                problems.push(format!(
+                    "{TOKEN_KEY} can't be set here, it would be committed with the repository"
+                ));
+            } else if !value.is_string() {
+                problems.push(format!("{TOKEN_KEY} should be a string"));
+            }
+        } else if !keys.contains(key) {
+            problems.push(format!("unknown key '{key}'{}", did_you_mean(key)));
+        } else if let Err(err) = check_value(key, value) {
+            problems.push(err.to_string());
+        }
+    }
+    problems
+}
+
+/// JSON Schema of the TOML settings files or, with `global`, of the global JSON file, so that
+/// editors can complete and check them
+pub fn json_schema(global: bool) -> Value {
+    let (mut schema, title) = if global {
+        (
+            schema_for!(Config).to_value(),
+            "git-issue-flow global config",
+        )
+    } else {
+        (
+            schema_for!(RepoConfig).to_value(),
+            "git-issue-flow settings",
+        )
+    };
+    if let Value::Object(schema) = &mut schema {
+        schema.insert("title".to_string(), Value::String(title.to_string()));
+    }
+    schema
+}
+
+fn schema_keys<T: JsonSchema>() -> Vec<String> {
+    schema_for!(T)
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| properties.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Key of the current repository in the global config file
+fn repo_key(repo: Option<&GitRepo>) -> Result<String> {
+    let repo = repo.ok_or_else(|| {
+        anyhow!(
+            "Not in a git repository, use --global --all-repos for the settings of every repository"
+        )
+    })?;
+    let (owner, name) = repo.get_repo_owner_and_name()?;
+    Ok(format!("{owner}/{name}"))
+}
+
+/// The object under `key`, created when missing
+fn object_entry<'a>(
+    map: &'a mut Map<String, Value>,
+    key: &str,
+) -> Option<&'a mut Map<String, Value>> {
+    map.entry(key)
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+}
+
+/// Rewrites a TOML file with `edit` applied, keeping its comments and formatting
+fn edit_toml_file(path: &Path, edit: impl FnOnce(&mut toml_edit::DocumentMut)) -> Result<()> {
+    let content = if path.exists() {
+        fs::read_to_string(path).with_context(|| format!("Failed to read config file: {path:?}"))?
+    } else {
+        String::new()
+    };
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse {} as TOML", path.display()))?;
+    edit(&mut document);
+    fs::write(path, document.to_string())
+        .with_context(|| format!("Failed to write config file: {path:?}"))
+}
+
+fn toml_value(value: &Value) -> Result<toml_edit::Value> {
+    Ok(match value {
+        Value::Bool(value) => (*value).into(),
+        Value::Number(number) => match number.as_i64() {
+            Some(number) => number.into(),
+            None => number.as_f64().unwrap_or_default().into(),
+        },
+        Value::String(value) => value.as_str().into(),
+        Value::Array(items) => items
+            .iter()
+            .map(toml_value)
+            .collect::<Result<toml_edit::Array>>()?
+            .into(),
+        Value::Object(fields) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (key, value) in fields {
+                table.insert(key, toml_value(value)?);
+            }
+            table.into()
+        }
+        Value::Null => return Err(anyhow!("TOML has no null, use 'config unset' instead")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> Settings {
+        Settings {
+            github_token: None,
+            repo: RepoConfig::default(),
+            profile: None,
+            host: DEFAULT_HOST.to_string(),
+            repository: None,
+            values: Map::new(),
+            origins: HashMap::new(),
+        }
+    }
+
+    fn layer(toml: &str) -> Map<String, Value> {
+        let table: toml::Table = toml::from_str(toml).unwrap();
+        match serde_json::to_value(table).unwrap() {
+            Value::Object(layer) => layer,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_let_higher_layers_win_and_remember_origins() {
+        let mut settings = empty();
+        let repo_file = Origin::Repository(PathBuf::from(".git-task.toml"));
+        settings
+            .apply(
+                repo_file.clone(),
+                layer("base_branch = \"develop\"\nlabels = [\"a\", \"b\"]\ndraft = true"),
+            )
+            .unwrap();
+        let local_file = Origin::Local(PathBuf::from(".git/git-task.toml"));
+        settings
+            .apply(local_file.clone(), layer("labels = [\"mine\"]"))
+            .unwrap();
+        let entries = settings.entries();
+        let entry = |key: &str| entries.iter().find(|(name, _, _)| name == key).unwrap();
+        assert_eq!(entry("base_branch").1, "develop");
+        assert_eq!(entry("base_branch").2, repo_file);
+        assert_eq!(entry("labels").1, serde_json::json!(["mine"]));
+        assert_eq!(entry("labels").2, local_file);
+        assert_eq!(entry("milestone").2, Origin::Default);
+    }
+
+    #[test]
+    fn should_reject_unknown_and_invalid_settings() {
+        let origin = Origin::Local(PathBuf::from("git-task.toml"));
+        let err = empty()
+            .apply(origin.clone(), layer("base_brnach = \"main\""))
+            .unwrap_err();
+        assert!(err.to_string().contains("Unknown setting 'base_brnach'"));
+        assert!(
+            empty()
+                .apply(origin.clone(), layer("draft = \"yes\""))
+                .is_err()
+        );
+        let err = empty()
+            .apply(origin, layer("branch_template = \"task/{slug}\""))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("'task/{slug}' has no {number}"));
+        assert!(check_value("branch_template", &Value::from("{number}-{slug}")).is_ok());
+    }
+
+    #[test]
+    fn should_apply_the_repository_entry_over_top_level_global_settings() {
+        let global = serde_json::json!({
+            "base_branch": "develop",
+            "draft": true,
+            "repos": {"acme/widgets": {"draft": false}},
+            "profiles": {},
+        });
+        let global = global.as_object().unwrap();
+        let (defaults, entry) = global_layers(global, Some("acme/widgets"));
+        let path = PathBuf::from("config.json");
+        let mut settings = empty();
+        settings
+            .apply(Origin::Global(path.clone()), defaults)
+            .unwrap();
+        let repo_entry = Origin::GlobalRepo(path.clone(), "acme/widgets".to_string());
+        settings.apply(repo_entry.clone(), entry).unwrap();
+        let entries = settings.entries();
+        let entry = |key: &str| entries.iter().find(|(name, _, _)| name == key).unwrap();
+        assert_eq!(entry("base_branch").2, Origin::Global(path));
+        assert_eq!(entry("draft").1, false);
+        assert_eq!(entry("draft").2, repo_entry);
+        let (_, other) = global_layers(global, Some("acme/gadgets"));
+        assert!(other.is_empty());
+
+        let problems = global_problems(
+            serde_json::json!({"draft": "yes", "labls": []})
+                .as_object()
+                .unwrap(),
+        );
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("Invalid value for draft"));
+        assert_eq!(problems[1], "unknown key 'labls', did you mean 'labels'?");
+    }
+
+    #[test]
+    fn should_read_environment_values_by_type() {
+        assert_eq!(value_from_str("draft", "true"), Value::Bool(true));
+        assert_eq!(value_from_str("milestone", "3"), serde_json::json!(3));
+        assert_eq!(value_from_str("base_branch", "123"), "123");
+        assert_eq!(
+            value_from_str("labels", "a, b"),
+            serde_json::json!(["a", "b"])
+        );
+        assert_eq!(
+            value_from_str("labels", "[\"a\"]"),
+            serde_json::json!(["a"])
+        );
+        assert_eq!(value_from_str("github_token", "42"), "42");
+    }
+
+    #[test]
+    fn should_report_every_problem_in_a_layer() {
+        let problems = layer_problems(
+            &layer(
+                "draft = \"yes\"\nbase_brnach = \"main\"\ngithub_token = \"x\"\nlabels = [\"a\"]",
+            ),
+            false,
+        );
+        assert_eq!(problems.len(), 3);
+        assert!(
+            problems
+                .iter()
+                .any(|problem| problem.contains("did you mean 'base_branch'?"))
+        );
+        assert!(
+            problems
+                .iter()
+                .any(|problem| problem.starts_with("Invalid value for draft"))
+        );
+        assert!(layer_problems(&layer("github_token = \"x\""), true).is_empty());
+        assert_eq!(did_you_mean("zzz"), "");
+    }
+
+    #[test]
+    fn should_find_the_app_key_next_to_the_config_file() {
+        let mut settings = empty();
+        settings.repository = Some(("acme".to_string(), "widgets".to_string()));
+        let repo_file = Origin::Repository(PathBuf::from("/work/widgets/.git-task.toml"));
+        settings
+            .apply(
+                repo_file,
+                layer("app_id = 1\napp_private_key = \"keys/app.pem\""),
+            )
+            .unwrap();
+        settings.repo =
+            serde_json::from_value(Value::Object(repo_values(&settings.values))).unwrap();
+        let app = settings.app().unwrap().unwrap();
+        assert_eq!(app.private_key, PathBuf::from("/work/widgets/keys/app.pem"));
+
+        let local_file = Origin::Local(PathBuf::from("/work/widgets/.git/git-task.toml"));
+        settings
+            .apply(local_file, layer("app_private_key = \"/etc/bot/app.pem\""))
+            .unwrap();
+        settings.repo =
+            serde_json::from_value(Value::Object(repo_values(&settings.values))).unwrap();
+        let app = settings.app().unwrap().unwrap();
+        assert_eq!(app.private_key, PathBuf::from("/etc/bot/app.pem"));
+    }
+
+    #[test]
+    fn should_convert_settings_to_toml() {
+        let value = serde_json::json!({"labels": ["a", "b"], "draft": true, "milestone": 3});
+        let mut document: toml_edit::DocumentMut =
+            "# kept\nbase_branch = \"main\"\n".parse().unwrap();
+        for (key, value) in value.as_object().unwrap() {
+            document[key.as_str()] = toml_edit::Item::Value(toml_value(value).unwrap());
+        }
+        let written = document.to_string();
+        assert!(written.starts_with("# kept\n"));
+        assert_eq!(layer(&written)["labels"], serde_json::json!(["a", "b"]));
+        assert_eq!(layer(&written)["milestone"], 3);
+        assert!(toml_value(&Value::Null).is_err());
+    }
+
+    #[tokio::test]
+    async fn should_call_the_api_of_the_profile_host() {
+        let enterprise = Profile {
+            host: Some("ghe.acme.com".to_string()),
+            ..Profile::default()
+        };
+        let host = api_host(None, Some(("enterprise", &enterprise))).unwrap();
+        assert_eq!(host, "ghe.acme.com");
+        let (api, uploads) = crate::api::endpoints(&host).unwrap();
+        assert_eq!(api, "https://ghe.acme.com/api/v3");
+        assert_eq!(uploads, "https://ghe.acme.com/api/uploads");
+        assert!(
+            crate::github::GitHubClient::new("token", &host, "acme".into(), "w".into()).is_ok()
+        );
+
+        let origin = RemoteUrl {
+            host: "github.com".to_string(),
+            owner: "acme".to_string(),
+            repo: "w".to_string(),
+        };
+        let err = api_host(Some(&origin), Some(("enterprise", &enterprise))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Profile 'enterprise' is for ghe.acme.com, but origin is on github.com"
+        );
+        let host = api_host(Some(&origin), None).unwrap();
+        assert_eq!(
+            crate::api::endpoints(&host).unwrap().0,
+            "https://api.github.com"
+        );
+        assert_eq!(api_host(None, None).unwrap(), DEFAULT_HOST);
+    }
+}
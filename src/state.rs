@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Local bookkeeping about tasks started in a repository, stored inside the git directory
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TaskState {
+    /// Tasks keyed by their branch name
+    #[serde(default)]
+    pub tasks: HashMap<String, Task>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Task {
+    pub issue: u64,
+    /// Draft pull request opened by `start --draft-pr`, flipped to ready by `finish`
+    pub draft_pr: Option<u64>,
+    /// Linked worktree created for the task by `start --worktree`
+    pub worktree: Option<PathBuf>,
+}
+
+impl TaskState {
+    pub fn load(git_dir: &Path) -> Result<Self> {
+        let path = git_dir.join("git-task").join("state.json");
+        if !path.exists() {
+            return Ok(TaskState {
+                path,
+                ..Default::default()
+            });
+        }
+        let state_str = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read task state: {path:?}"))?;
+        let mut state: TaskState = serde_json::from_str(&state_str)
+            .with_context(|| "Failed to parse task state as JSON")?;
+        state.path = path;
+        Ok(state)
+    }
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create a state directory: {parent:?}"))?;
+        }
+        let state_str = serde_json::to_string_pretty(self)
+            .with_context(|| "Failed to serialize task state to JSON")?;
+        fs::write(&self.path, state_str)
+            .with_context(|| format!("Failed to write task state: {:?}", self.path))?;
+        Ok(())
+    }
+    pub fn task(&self, branch: &str) -> Option<&Task> {
+        self.tasks.get(branch)
+    }
+    pub fn task_mut(&mut self, branch: &str, issue: u64) -> &mut Task {
+        self.tasks
+            .entry(branch.to_string())
+            .or_insert_with(|| Task {
+                issue,
+                ..Default::default()
+            })
+    }
+    pub fn remove(&mut self, branch: &str) {
+        self.tasks.remove(branch);
+    }
+}
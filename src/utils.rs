@@ -1,6 +1,7 @@
 use crate::{github::Issue, inputs::InputProvider};
 use anyhow::Result;
 use colored::Colorize;
+use std::path::{Component, Path, PathBuf};
 
 pub fn select_issue<'a>(
     issues: &'a [Issue],
@@ -36,8 +37,108 @@ pub fn select_issue<'a>(
     Ok(&issues[selection])
 }
 
-pub fn create_branch_name_from_issue(issue: &Issue) -> String {
-    format!("feature/{}", issue.number)
+/// Names the branch of an issue after a template like `feature/{number}-{slug}`
+pub fn create_branch_name_from_issue(issue: &Issue, template: &str) -> String {
+    template
+        .replace("{number}", &issue.number.to_string())
+        .replace("{slug}", &slugify(&issue.title))
+}
+
+/// Lowercase words of a title joined with dashes, short enough for a branch name
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if slug.len() + word.len() > 40 {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    slug
+}
+
+/// Extracts the issue number from a task branch named after `template`, like `feature/42`
+pub fn issue_number_from_branch(branch: &str, template: &str) -> Option<u64> {
+    let (prefix, suffix) = template.split_once("{number}")?;
+    let mut start = 0;
+    while start < branch.len() {
+        let digits = branch[start..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if digits == 0 {
+            start += branch[start..].chars().next().map_or(1, char::len_utf8);
+            continue;
+        }
+        let end = start + digits;
+        if template_part_matches(prefix, &branch[..start])
+            && template_part_matches(suffix, &branch[end..])
+        {
+            return branch[start..end].parse().ok();
+        }
+        start = end;
+    }
+    None
+}
+
+/// Matches the text around the number of a branch template, `{slug}` stands for any text
+fn template_part_matches(part: &str, text: &str) -> bool {
+    let pieces: Vec<&str> = part.split("{slug}").collect();
+    let (first, last) = (pieces[0], pieces[pieces.len() - 1]);
+    if pieces.len() == 1 {
+        return part == text;
+    }
+    if text.len() < first.len() + last.len() || !text.starts_with(first) || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for piece in &pieces[1..pieces.len() - 1] {
+        match rest.find(piece) {
+            Some(index) => rest = &rest[index + piece.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Fills `{repo}` and `{number}` in a worktree directory template like `../{repo}-{number}`
+pub fn expand_worktree_template(template: &str, repo: &str, issue_number: u64) -> String {
+    template
+        .replace("{repo}", repo)
+        .replace("{number}", &issue_number.to_string())
+}
+
+/// Resolves `.` and `..` components without touching the filesystem
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Combines configured defaults with values given on the command line, dropping duplicates
+pub fn merge_unique(defaults: &[String], extra: &[String]) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for value in defaults.iter().chain(extra) {
+        if !result.contains(value) {
+            result.push(value.clone());
+        }
+    }
+    result
 }
 
 pub fn create_pr_text(issue_number: u64, desc: &str) -> String {
@@ -64,8 +165,70 @@ mod tests {
             url: "https://github.com/test/tester/issues/48".to_string(),
             labels: vec![],
         };
-        let branch_name = create_branch_name_from_issue(&issue);
+        let branch_name = create_branch_name_from_issue(&issue, "feature/{number}");
         assert_eq!(branch_name, "feature/48".to_string());
+        let branch_name = create_branch_name_from_issue(&issue, "{number}-{slug}");
+        assert_eq!(branch_name, "48-test-issue".to_string());
+    }
+    #[test]
+    fn should_parse_issue_number_from_branch() {
+        assert_eq!(
+            issue_number_from_branch("feature/48", "feature/{number}"),
+            Some(48)
+        );
+        assert_eq!(issue_number_from_branch("main", "feature/{number}"), None);
+        assert_eq!(
+            issue_number_from_branch("feature/abc", "feature/{number}"),
+            None
+        );
+        assert_eq!(
+            issue_number_from_branch("bugfix/48", "feature/{number}"),
+            None
+        );
+    }
+    #[test]
+    fn should_parse_issue_number_from_templated_branch() {
+        let template = "task/{number}-{slug}";
+        assert_eq!(
+            issue_number_from_branch("task/12-fix-3-bugs", template),
+            Some(12)
+        );
+        assert_eq!(issue_number_from_branch("task/12", template), None);
+        assert_eq!(
+            issue_number_from_branch("fix-3-bugs-12", "{slug}-{number}"),
+            Some(12)
+        );
+    }
+    #[test]
+    fn should_expand_worktree_template() {
+        assert_eq!(
+            expand_worktree_template("../{repo}-{number}", "tester", 48),
+            "../tester-48"
+        );
+        assert_eq!(
+            expand_worktree_template("/tmp/worktrees/{number}", "tester", 7),
+            "/tmp/worktrees/7"
+        );
+    }
+    #[test]
+    fn should_normalize_paths() {
+        assert_eq!(
+            normalize_path(Path::new("/work/tester/../tester-48")),
+            PathBuf::from("/work/tester-48")
+        );
+        assert_eq!(
+            normalize_path(Path::new("/work/./tester/")),
+            PathBuf::from("/work/tester")
+        );
+    }
+    #[test]
+    fn should_merge_defaults_with_extra_values() {
+        let defaults = vec!["alice".to_string(), "bob".to_string()];
+        let extra = vec!["bob".to_string(), "carol".to_string()];
+        assert_eq!(
+            merge_unique(&defaults, &extra),
+            vec!["alice", "bob", "carol"]
+        );
     }
     #[test]
     fn should_create_pr_text_with_description() {